@@ -0,0 +1,254 @@
+//! Animated GIF and APNG exports of element animations, for showing hats to
+//! people who do not run the game.
+
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Result};
+use bevy_math::IVec2;
+use image::codecs::gif::{GifEncoder, Repeat};
+use image::{Delay, Frame, Rgba, RgbaImage};
+use pixas::bitmap::Bitmap;
+use serde::{Deserialize, Serialize};
+
+use crate::animations::{Animation, DUCK_GAME_HERTZ};
+use crate::hats::AbstractHat;
+
+/// The colors and cell size of the checkerboard behind the animation preview.
+const CHECKER_COLORS: [[u8; 3]; 2] = [[192, 192, 192], [128, 128, 128]];
+const CHECKER_CELL: u32 = 8;
+pub const MAX_EXPORT_SCALE: u32 = 16;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ExportFormat {
+    #[default]
+    Gif,
+    Apng,
+}
+
+impl ExportFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Gif => "gif",
+            ExportFormat::Apng => "png",
+        }
+    }
+
+    /// Picks the format by extension, `.png` and `.apng` files are APNGs.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_lowercase();
+        match extension.as_str() {
+            "gif" => Some(ExportFormat::Gif),
+            "png" | "apng" => Some(ExportFormat::Apng),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ExportBackground {
+    #[default]
+    Transparent,
+    Checkerboard,
+    Solid([u8; 3]),
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExportOptions {
+    pub format: ExportFormat,
+    /// Every pixel becomes a `scale` by `scale` square.
+    pub scale: u32,
+    pub background: ExportBackground,
+}
+
+impl Default for ExportOptions {
+    fn default() -> Self {
+        Self {
+            format: ExportFormat::default(),
+            scale: 1,
+            background: ExportBackground::default(),
+        }
+    }
+}
+
+/// How long a frame of an animation with `delay` stays on screen.
+pub fn frame_duration_ms(delay: i32) -> u32 {
+    (delay.max(1) as f32 * 1000.0 / DUCK_GAME_HERTZ).round() as u32
+}
+
+/// Cuts the animation frames out of the element image, the way the animation
+/// shader does: frames are numbered row by row over the whole image.
+pub fn render_frames(
+    element: &dyn AbstractHat,
+    animation: &Animation,
+    options: &ExportOptions,
+) -> Result<Vec<(RgbaImage, u32)>> {
+    let base = element.base();
+    let Some(bitmap) = &base.bitmap else {
+        bail!("{} has no image", base.hat_type);
+    };
+    let frame_size = base.frame_size;
+    if frame_size.x <= 0 || frame_size.y <= 0 {
+        bail!("{} has no frame size", base.hat_type);
+    }
+    if animation.frames.is_empty() {
+        bail!("animation {} has no frames", animation.anim_type);
+    }
+    let frames_per_row = (bitmap.width as i32 / frame_size.x).max(1);
+    let duration = frame_duration_ms(animation.delay);
+    Ok(animation
+        .frames
+        .iter()
+        .map(|frame| {
+            let position =
+                IVec2::new(frame.value % frames_per_row, frame.value / frames_per_row) * frame_size;
+            (
+                render_frame(bitmap, position, frame_size, options),
+                duration,
+            )
+        })
+        .collect())
+}
+
+fn render_frame(
+    bitmap: &Bitmap,
+    position: IVec2,
+    frame_size: IVec2,
+    options: &ExportOptions,
+) -> RgbaImage {
+    let scale = options.scale.clamp(1, MAX_EXPORT_SCALE);
+    let (width, height) = (frame_size.x as u32 * scale, frame_size.y as u32 * scale);
+    RgbaImage::from_fn(width, height, |x, y| {
+        let (source_x, source_y) = ((x / scale) as i32, (y / scale) as i32);
+        let pixel = bitmap
+            .get_pixel(position.x + source_x, position.y + source_y)
+            .map(|p| [p.r, p.g, p.b, p.a])
+            .unwrap_or([0; 4]);
+        let background = match options.background {
+            ExportBackground::Transparent => return Rgba(pixel),
+            ExportBackground::Solid(color) => color,
+            ExportBackground::Checkerboard => {
+                let cell = (source_x as u32 / CHECKER_CELL + source_y as u32 / CHECKER_CELL) % 2;
+                CHECKER_COLORS[cell as usize]
+            }
+        };
+        let alpha = pixel[3] as u32;
+        let mix = |i: usize| {
+            ((pixel[i] as u32 * alpha + background[i] as u32 * (255 - alpha)) / 255) as u8
+        };
+        Rgba([mix(0), mix(1), mix(2), 255])
+    })
+}
+
+pub fn export_animation(
+    element: &dyn AbstractHat,
+    animation: &Animation,
+    path: &Path,
+    options: &ExportOptions,
+) -> Result<()> {
+    let frames = render_frames(element, animation, options)?;
+    let format = ExportFormat::from_path(path).unwrap_or(options.format);
+    let writer = BufWriter::new(File::create(path)?);
+    match format {
+        ExportFormat::Gif => write_gif(writer, frames),
+        ExportFormat::Apng => write_apng(writer, frames),
+    }
+}
+
+/// Exports every animation of the element into `dir`, one file per animation
+/// named after its type. Returns the written files.
+pub fn export_all_animations(
+    element: &dyn AbstractHat,
+    dir: &Path,
+    options: &ExportOptions,
+) -> Result<Vec<PathBuf>> {
+    let mut paths = vec![];
+    for animation in element.animations().into_iter().flatten() {
+        let animation = animation.borrow();
+        if animation.frames.is_empty() {
+            continue;
+        }
+        let file_name = format!("{:?}.{}", animation.anim_type, options.format.extension());
+        let path = dir.join(file_name);
+        export_animation(element, &animation, &path, options)?;
+        paths.push(path);
+    }
+    Ok(paths)
+}
+
+fn write_gif(writer: BufWriter<File>, frames: Vec<(RgbaImage, u32)>) -> Result<()> {
+    let mut encoder = GifEncoder::new(writer);
+    encoder.set_repeat(Repeat::Infinite)?;
+    encoder.encode_frames(frames.into_iter().map(|(image, duration)| {
+        Frame::from_parts(image, 0, 0, Delay::from_numer_denom_ms(duration, 1))
+    }))?;
+    Ok(())
+}
+
+fn write_apng(writer: BufWriter<File>, frames: Vec<(RgbaImage, u32)>) -> Result<()> {
+    let Some((first, _)) = frames.first() else {
+        bail!("there are no frames to write");
+    };
+    let mut encoder = png::Encoder::new(writer, first.width(), first.height());
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_animated(frames.len() as u32, 0)?;
+    let mut writer = encoder.write_header()?;
+    for (image, duration) in &frames {
+        writer.set_frame_delay((*duration).min(u16::MAX as u32) as u16, 1000)?;
+        writer.write_image_data(image.as_raw())?;
+    }
+    writer.finish()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use bevy_math::IVec2;
+    use pixas::bitmap::Bitmap;
+    use pixas::pixel::Pixel;
+
+    use crate::animation_export::{
+        frame_duration_ms, render_frames, ExportBackground, ExportOptions,
+    };
+    use crate::animations::{AnimType, Animation};
+    use crate::hats::{HatBase, HatType, Wereable};
+
+    #[test]
+    fn cuts_frames_like_the_shader() {
+        // two rows of two frames, the top left pixel of each holds its number in red
+        let mut bitmap = Bitmap::with_size(4, 4);
+        for frame in 0..4u8 {
+            let (x, y) = ((frame % 2) as i32 * 2, (frame / 2) as i32 * 2);
+            bitmap.set_pixel(x, y, Pixel::from_rgba(frame * 50, 0, 0, 255));
+        }
+        let wereable = Wereable {
+            base: HatBase {
+                hat_type: HatType::Wereable,
+                frame_size: IVec2::new(2, 2),
+                hat_area_size: IVec2::new(4, 4),
+                bitmap: Some(Rc::new(bitmap)),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let animation = Animation::new(AnimType::OnDefault, 6, true, vec![3.into(), 1.into()]);
+        let options = ExportOptions {
+            scale: 2,
+            background: ExportBackground::Solid([0, 0, 255]),
+            ..Default::default()
+        };
+
+        let frames = render_frames(&wereable, &animation, &options).unwrap();
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].1, frame_duration_ms(6));
+        assert_eq!(frames[0].1, 100);
+        assert_eq!(frames[0].0.dimensions(), (4, 4));
+        assert_eq!(frames[0].0.get_pixel(1, 1).0, [150, 0, 0, 255]);
+        assert_eq!(frames[1].0.get_pixel(0, 0).0, [50, 0, 0, 255]);
+        assert_eq!(frames[1].0.get_pixel(3, 3).0, [0, 0, 255, 255]);
+    }
+}
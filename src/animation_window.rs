@@ -5,16 +5,15 @@ use std::sync::Arc;
 
 use bevy_math::IVec2;
 use bevy_math::Vec2;
-use eframe::egui::{Button, CollapsingHeader, Id, Pos2, Rect, Ui, Window};
+use eframe::egui::{Button, CollapsingHeader, Id, Painter, Pos2, Rect, Ui, Window};
 use eframe::glow::Context;
 use eframe::glow::{self, HasContext};
 use once_cell::sync::Lazy;
 
-use crate::animations::AnimType;
+use crate::animations::{AnimType, DUCK_GAME_HERTZ};
 use crate::{animations::Animation, shader::Shader, texture::Texture, VERTEX_ARRAY};
 use crate::{egui_utils, AnimationWindowAction};
 
-const DUCK_GAME_HERTZ: f32 = 60.0;
 const MAX_SYMBOL_WIDTH: i32 = 16;
 pub const TEXTURES_SCALE_FACTOR: f32 = 5.0;
 
@@ -22,6 +21,8 @@ pub const TEXTURES_SCALE_FACTOR: f32 = 5.0;
 pub struct AnimationWindow {
     pub current_anim_index: usize,
     pub current_frame_index: usize,
+    /// Frame of the sheet shown last time the window was drawn.
+    pub current_frame: i32,
     frame_timer: f32,
     paused: bool,
     default_anim: AnimationCell,
@@ -52,6 +53,26 @@ fn draw_texture(gl: &Context, texture: crate::texture::Inner, shader: Shader, un
     }
 }
 
+/// Paints the whole texture into `rect` over the checkerboard of the animation preview.
+pub fn paint_sheet(painter: &Painter, rect: Rect, texture: &Texture, shader: Shader, time: f32) {
+    let size = Vec2::new(texture.width() as f32, texture.height() as f32);
+    let uniforms = Uniforms {
+        offset: Vec2::ZERO,
+        time,
+        frames_amount: Vec2::ONE,
+        frame_size: size,
+        current_frame: 0.0,
+    };
+    let inner = texture.inner();
+    let callback = eframe::egui::PaintCallback {
+        rect,
+        callback: Arc::new(egui_glow::CallbackFn::new(move |_, painter| {
+            draw_texture(painter.gl(), inner, shader.clone(), uniforms)
+        })),
+    };
+    painter.add(callback);
+}
+
 type AnimationCell = Rc<RefCell<Animation>>;
 
 pub struct AnimationWindowFrameData<'a> {
@@ -72,6 +93,7 @@ impl AnimationWindow {
             paused: false,
             current_anim_index: 0,
             current_frame_index: 0,
+            current_frame: 0,
             frame_timer: 0.0,
             default_anim: RefCell::new(Animation::new(
                 AnimType::OnDefault,
@@ -169,10 +191,11 @@ impl AnimationWindow {
                             .map(|f| f.value)
                             .unwrap_or(0)
                     })
-                    .unwrap_or_default() as f32;
+                    .unwrap_or_default();
+                self.current_frame = current_frame;
                 let uniforms = Uniforms {
                     offset: (offset.max.x, offset.min.y).into(),
-                    current_frame,
+                    current_frame: current_frame as f32,
                     frames_amount: Vec2::new(
                         (data.texture.width() / data.frame_size.x) as f32,
                         (data.texture.height() / data.frame_size.y) as f32,
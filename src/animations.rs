@@ -1,6 +1,9 @@
 use std::{cell::Cell, fmt::Display};
 
+use derivative::Derivative;
+use num_traits::FromPrimitive;
 use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
 
 use crate::{
     hats::HatType,
@@ -9,6 +12,14 @@ use crate::{
     prelude::{Metapixel, MetapixelType},
 };
 use AnimType as AT;
+
+/// Ticks per second of the game, animation delays count these.
+pub const DUCK_GAME_HERTZ: f32 = 60.0;
+
+/// Converts milliseconds into game ticks, the unit animation delays are in.
+pub fn delay_from_duration(milliseconds: u32) -> i32 {
+    (milliseconds as f32 * DUCK_GAME_HERTZ / 1000.0).round() as i32
+}
 //TODO: add on death/ressurect animations?
 pub static WINGS_ANIMATIONS: Lazy<Vec<AnimType>> = Lazy::new(|| {
     vec![
@@ -37,17 +48,27 @@ pub static WEREABLE_ANIMATIONS: Lazy<Vec<AnimType>> = Lazy::new(|| {
         AT::OnRessurect,
     ]
 });
+pub static EXTRA_ANIMATIONS: Lazy<Vec<AnimType>> = Lazy::new(|| {
+    vec![
+        AT::OnDefault,
+        AT::OnPressQuack,
+        AT::OnReleaseQuack,
+        AT::OnDuckDeath,
+        AT::OnRessurect,
+    ]
+});
 
 pub fn avalible_animations<'a>(hat_type: HatType) -> Option<&'a [AnimType]> {
     match hat_type {
         HatType::Wereable => Some(&WEREABLE_ANIMATIONS),
         HatType::Wings => Some(&WINGS_ANIMATIONS),
+        HatType::Extra => Some(&EXTRA_ANIMATIONS),
         HatType::FlyingPet | HatType::WalkingPet => Some(&PET_AIMATIONS),
         _ => None,
     }
 }
 
-#[derive(Copy, Clone, Debug, FromPrimitive, PartialEq, Eq)]
+#[derive(Copy, Clone, Debug, FromPrimitive, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum AnimType {
     OnDefault,
     OnPressQuack,
@@ -64,6 +85,15 @@ pub enum AnimType {
     Unspecified,
 }
 
+impl AnimType {
+    /// Every animation type, `Unspecified` aside.
+    pub fn all() -> impl Iterator<Item = AnimType> {
+        (0..)
+            .map_while(AnimType::from_i32)
+            .filter(|t| *t != AnimType::Unspecified)
+    }
+}
+
 impl Display for AnimType {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let name = match self {
@@ -151,14 +181,19 @@ pub fn frame_id() -> FrameId {
     FrameId(id)
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Derivative)]
+#[derivative(Debug, Hash)]
 pub struct Animation {
     pub anim_type: AnimType,
     pub delay: i32,
     pub looping: bool,
     pub frames: Vec<Frame>,
+    // what the user is typing in to add, not part of the animation itself
+    #[derivative(Debug = "ignore", Hash = "ignore")]
     pub new_frame: i32,
+    #[derivative(Debug = "ignore", Hash = "ignore")]
     pub new_range_start: i32,
+    #[derivative(Debug = "ignore", Hash = "ignore")]
     pub new_range_end: i32,
 }
 
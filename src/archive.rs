@@ -0,0 +1,312 @@
+//! Hats packed into zip archives, which is how they usually get shared.
+//!
+//! Archives are decoded in memory. Element paths point inside the archive, like
+//! `Hats/Fridge.zip/Fridge/hat_32_32.png`, so they name the element but can not be
+//! opened on their own.
+
+use std::fs::File;
+use std::io::{Cursor, Read, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Result};
+use pixas::bitmap::Bitmap;
+use pixas::pixel::Pixel;
+use serde::Serialize;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+use crate::file_utils::FileStemString;
+use crate::hat_utils::get_name_and_size;
+use crate::hats::{is_element_file, AbstractHat, Hat, HatLoadReport, HatType};
+
+const MANIFEST_FILE: &str = "manifest.json";
+
+/// Lists what is in an exported archive, for whoever receives it.
+#[derive(Debug, Serialize)]
+struct Manifest {
+    name: String,
+    elements: Vec<ManifestElement>,
+}
+
+#[derive(Debug, Serialize)]
+struct ManifestElement {
+    file: String,
+    hat_type: String,
+    frame_size: [i32; 2],
+    area_size: [i32; 2],
+    animations: Vec<String>,
+}
+
+pub fn is_zip(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case("zip"))
+}
+
+/// Loads a hat folder, or a zipped one.
+pub fn load_hat(path: impl AsRef<Path>) -> Result<(Hat, HatLoadReport)> {
+    match is_zip(path.as_ref()) {
+        true => load_zip(path),
+        false => Hat::load_with_report(path),
+    }
+}
+
+/// Saves into whatever the path points at, a folder or an archive.
+pub fn save_hat(hat: &mut Hat, path: impl AsRef<Path>) -> Result<()> {
+    match is_zip(path.as_ref()) {
+        true => save_zip(hat, path, false),
+        false => hat.save(path),
+    }
+}
+
+/// Decodes every element in the archive, wherever it is inside. The hat remembers
+/// the archive as its path. Archives with elements in several folders hold several
+/// hats and are refused.
+pub fn load_zip(path: impl AsRef<Path>) -> Result<(Hat, HatLoadReport)> {
+    let path = path.as_ref();
+    let mut archive = ZipArchive::new(File::open(path)?)?;
+    let mut hat = Hat::new(path.to_path_buf());
+    let mut report = HatLoadReport::default();
+
+    // Sorted, so that when two files describe the same element the same one always wins.
+    let mut names = archive.file_names().map(str::to_owned).collect::<Vec<_>>();
+    names.sort();
+    let mut folders = names
+        .iter()
+        .filter(|name| is_element_file(Path::new(name)))
+        .map(|name| element_folder(name))
+        .collect::<Vec<_>>();
+    folders.sort();
+    folders.dedup();
+    if folders.len() > 1 {
+        bail!(
+            "{} holds more than one hat, in {}",
+            path.display(),
+            folders.join(", ")
+        );
+    }
+    for name in names {
+        if !is_element_file(Path::new(&name)) {
+            continue;
+        }
+        let element_path = path.join(&name);
+        if let Err(err) = load_entry(&mut archive, &name, element_path.clone(), &mut hat) {
+            report.failed_files.push((element_path, format!("{err:#}")));
+        }
+    }
+    report.collect_metapixel_errors(&hat);
+    Ok((hat, report))
+}
+
+/// The folder an entry is in, with the trailing slash, empty at the root.
+fn element_folder(name: &str) -> &str {
+    name.rfind('/').map_or("", |i| &name[..=i])
+}
+
+fn load_entry(
+    archive: &mut ZipArchive<File>,
+    name: &str,
+    element_path: PathBuf,
+    hat: &mut Hat,
+) -> Result<()> {
+    let mut bytes = vec![];
+    archive.by_name(name)?.read_to_end(&mut bytes)?;
+    let bitmap = decode_png(&bytes)?;
+    let name_and_size = get_name_and_size(&element_path.file_stem_string().unwrap_or_default());
+    let Some(hat_type) = HatType::from_file_name(&name_and_size.name) else {
+        bail!("{name} is not an element");
+    };
+    hat.add_decoded_element(hat_type, bitmap, name_and_size, element_path)
+}
+
+/// Writes every element into a zip at `path`, with a manifest if asked to. When the
+/// archive already exists, its other files, like readmes, are carried over and
+/// a manifest it had is kept up to date.
+pub fn save_zip(hat: &Hat, path: impl AsRef<Path>, with_manifest: bool) -> Result<()> {
+    let path = path.as_ref();
+    let mut elements = vec![];
+    for element in hat.iter_all_elements() {
+        let (file_name, bitmap) = hat.encode_element(element.id())?;
+        elements.push((element, file_name, encode_png(&bitmap)?));
+    }
+    let mut old_archive = match path.exists() {
+        true => Some(ZipArchive::new(File::open(path)?)?),
+        false => None,
+    };
+    let with_manifest = with_manifest
+        || old_archive
+            .as_ref()
+            .is_some_and(|a| a.file_names().any(|name| is_manifest(Path::new(name))));
+    // keep the elements where the archive had them, usually a folder named after the hat
+    let folder = match &old_archive {
+        Some(archive) => archive
+            .file_names()
+            .find(|name| is_element_file(Path::new(name)))
+            .map(|name| element_folder(name).to_string())
+            .unwrap_or_default(),
+        None => format!("{}/", path.file_stem_string().unwrap_or_default()),
+    };
+
+    let manifest = with_manifest.then(|| Manifest {
+        name: path.file_stem_string().unwrap_or_default(),
+        elements: elements
+            .iter()
+            .map(|(element, file_name, _)| manifest_element(*element, file_name))
+            .collect(),
+    });
+    let files = elements
+        .into_iter()
+        .map(|(_, file_name, png)| (format!("{folder}{file_name}"), png));
+
+    let temp_path = path.with_extension("zip.tmp");
+    let result = write_archive(
+        File::create(&temp_path)?,
+        old_archive.as_mut(),
+        files,
+        manifest.map(|m| (format!("{folder}{MANIFEST_FILE}"), m)),
+    );
+    // an open file can not be replaced on every platform
+    drop(old_archive);
+    if let Err(err) = result {
+        std::fs::remove_file(&temp_path).ok();
+        return Err(err);
+    }
+    std::fs::rename(&temp_path, path)?;
+    Ok(())
+}
+
+fn write_archive(
+    file: File,
+    old_archive: Option<&mut ZipArchive<File>>,
+    files: impl Iterator<Item = (String, Vec<u8>)>,
+    manifest: Option<(String, Manifest)>,
+) -> Result<()> {
+    let mut writer = ZipWriter::new(file);
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    if let Some(archive) = old_archive {
+        for i in 0..archive.len() {
+            let entry = archive.by_index_raw(i)?;
+            let name = Path::new(entry.name());
+            if !is_element_file(name) && !is_manifest(name) {
+                writer.raw_copy_file(entry)?;
+            }
+        }
+    }
+    for (name, png) in files {
+        writer.start_file(name, options)?;
+        writer.write_all(&png)?;
+    }
+    if let Some((name, manifest)) = manifest {
+        writer.start_file(name, options)?;
+        writer.write_all(serde_json::to_string_pretty(&manifest)?.as_bytes())?;
+    }
+    writer.finish()?;
+    Ok(())
+}
+
+fn is_manifest(path: &Path) -> bool {
+    path.file_name().is_some_and(|n| n == MANIFEST_FILE)
+}
+
+fn manifest_element(element: &dyn AbstractHat, file_name: &str) -> ManifestElement {
+    let base = element.base();
+    ManifestElement {
+        file: file_name.to_string(),
+        hat_type: base.hat_type.to_string(),
+        frame_size: base.frame_size.to_array(),
+        area_size: base.hat_area_size.to_array(),
+        animations: element
+            .animations()
+            .into_iter()
+            .flatten()
+            .map(|a| a.borrow().anim_type.to_string())
+            .collect(),
+    }
+}
+
+pub fn decode_png(bytes: &[u8]) -> Result<Bitmap> {
+    let image = image::load_from_memory(bytes)?.to_rgba8();
+    let mut bitmap = Bitmap::with_size(image.width(), image.height());
+    for (x, y, pixel) in image.enumerate_pixels() {
+        let [r, g, b, a] = pixel.0;
+        bitmap.set_pixel(x as i32, y as i32, Pixel::from_rgba(r, g, b, a));
+    }
+    Ok(bitmap)
+}
+
+pub fn encode_png(bitmap: &Bitmap) -> Result<Vec<u8>> {
+    let data = bitmap.get_pixel_data().to_vec();
+    let Some(image) = image::RgbaImage::from_raw(bitmap.width, bitmap.height, data) else {
+        bail!("bitmap data does not match its size");
+    };
+    let mut bytes = Cursor::new(vec![]);
+    image.write_to(&mut bytes, image::ImageFormat::Png)?;
+    Ok(bytes.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use zip::write::SimpleFileOptions;
+    use zip::ZipWriter;
+
+    use crate::archive::{load_zip, save_zip};
+    use crate::hats::Hat;
+
+    fn encoded(hat: &Hat) -> Vec<(String, Vec<u8>)> {
+        let mut files = hat
+            .encode()
+            .unwrap()
+            .into_iter()
+            .map(|(name, bitmap)| (name, bitmap.get_pixel_data().to_vec()))
+            .collect::<Vec<_>>();
+        files.sort_by(|a, b| a.0.cmp(&b.0));
+        files
+    }
+
+    #[test]
+    fn loads_the_same_hat_as_the_folder() {
+        let (from_zip, report) = load_zip("Hats/ChaosFridge.zip").unwrap();
+        let from_folder = Hat::load("Hats/ChaosFridge").unwrap();
+        assert!(report.failed_files.is_empty());
+        assert_eq!(encoded(&from_zip), encoded(&from_folder));
+    }
+
+    #[test]
+    fn exported_zip_loads_back() {
+        let dir = std::env::temp_dir().join("hpp_exported_zip_loads_back");
+        std::fs::remove_dir_all(&dir).ok();
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("ChaosFridge.zip");
+        std::fs::copy("Hats/ChaosFridge.zip", &path).unwrap();
+
+        let (hat, _) = load_zip(&path).unwrap();
+        save_zip(&hat, &path, true).unwrap();
+        let (reloaded, _) = load_zip(&path).unwrap();
+        assert_eq!(encoded(&reloaded), encoded(&hat));
+
+        let archive = zip::ZipArchive::new(std::fs::File::open(&path).unwrap()).unwrap();
+        let mut names = archive.file_names().collect::<Vec<_>>();
+        names.sort();
+        assert!(names.contains(&"ChaosFridge/manifest.json"));
+        assert!(names.iter().all(|n| n.starts_with("ChaosFridge/")));
+        std::fs::remove_dir_all(dir).ok();
+    }
+
+    #[test]
+    fn refuses_archives_with_several_hats() {
+        let dir = std::env::temp_dir().join("hpp_refuses_several_hats");
+        std::fs::remove_dir_all(&dir).ok();
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("Hats.zip");
+        let mut writer = ZipWriter::new(std::fs::File::create(&path).unwrap());
+        // refused before anything is decoded, so the entries can stay empty
+        for name in ["Fridge/hat.png", "Chair/hat.png"] {
+            writer
+                .start_file(name, SimpleFileOptions::default())
+                .unwrap();
+        }
+        writer.finish().unwrap();
+
+        assert!(load_zip(&path).is_err());
+        std::fs::remove_dir_all(dir).ok();
+    }
+}
@@ -0,0 +1,171 @@
+//! Elements straight from Aseprite files.
+//!
+//! Every frame is flattened and packed into a sheet, and tags named after an
+//! [`AnimType`], like `OnPressQuack` or `on press quack`, become looping animations.
+
+use std::cell::RefCell;
+use std::path::Path;
+use std::rc::Rc;
+
+use anyhow::{anyhow, Result};
+use asefile::{AnimationDirection, AsepriteFile};
+use bevy_math::IVec2;
+use pixas::bitmap::Bitmap;
+use pixas::pixel::Pixel;
+
+use crate::animations::{avalible_animations, delay_from_duration, AnimType, Animation};
+use crate::build::pack_frames;
+use crate::file_utils::FileStemString;
+use crate::hat_utils::HatNameAndSize;
+use crate::hats::LoadHat;
+
+#[derive(Debug)]
+pub struct AsepriteSheet {
+    pub sheet: Bitmap,
+    pub frame_size: IVec2,
+    pub animations: Vec<Animation>,
+}
+
+pub fn is_aseprite(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case("aseprite") || e.eq_ignore_ascii_case("ase"))
+}
+
+/// Flattens the file into a sheet with `columns` frames per row, all in one row by
+/// default. Frames are cut at `frame_size`, the canvas size by default.
+pub fn import_aseprite(
+    path: &Path,
+    frame_size: Option<IVec2>,
+    columns: Option<usize>,
+) -> Result<AsepriteSheet> {
+    let ase = AsepriteFile::read_file(path)
+        .map_err(|err| anyhow!("could not read {}: {err}", path.display()))?;
+    let frame_size = frame_size.unwrap_or(IVec2::new(ase.width() as i32, ase.height() as i32));
+    let frames = (0..ase.num_frames())
+        .map(|i| frame_bitmap(&ase, i, frame_size))
+        .collect::<Vec<_>>();
+    let sheet = pack_frames(&frames, frame_size, columns.unwrap_or(frames.len()))?;
+
+    let mut animations = vec![];
+    for i in 0..ase.num_tags() {
+        let tag = ase.tag(i);
+        let Some(anim_type) = anim_type_from_name(tag.name()) else {
+            continue;
+        };
+        let (from, to) = (tag.from_frame(), tag.to_frame());
+        let total_duration = (from..=to).map(|f| ase.frame(f).duration()).sum::<u32>();
+        let delay = delay_from_duration(total_duration / (to - from + 1));
+        let direction = match tag.animation_direction() {
+            AnimationDirection::Reverse => TagDirection::Reverse,
+            AnimationDirection::PingPong => TagDirection::PingPong,
+            _ => TagDirection::Forward,
+        };
+        let frames = tag_frames(from as i32, to as i32, direction);
+        animations.push(Animation::new(
+            anim_type,
+            delay,
+            true,
+            frames.into_iter().map(Into::into).collect(),
+        ));
+    }
+    Ok(AsepriteSheet {
+        sheet,
+        frame_size,
+        animations,
+    })
+}
+
+/// Decodes an element from an Aseprite file, keeping only the animations its type has.
+pub fn load_element<T: LoadHat>(path: &Path) -> Result<T> {
+    let imported = import_aseprite(path, None, None)?;
+    let size = IVec2::new(imported.sheet.width as i32, imported.sheet.height as i32);
+    let name = path.file_stem_string().unwrap_or_default();
+    let mut element = T::from_bitmap(imported.sheet, HatNameAndSize::new(name, Some(size)))?;
+    element.base_mut().frame_size = imported.frame_size;
+    let allowed = avalible_animations(element.base().hat_type).unwrap_or_default();
+    if let Some(animations) = element.animations_mut() {
+        *animations = imported
+            .animations
+            .into_iter()
+            .filter(|a| allowed.contains(&a.anim_type))
+            .map(|a| Rc::new(RefCell::new(a)))
+            .collect();
+    }
+    Ok(element)
+}
+
+fn frame_bitmap(ase: &AsepriteFile, index: u32, frame_size: IVec2) -> Bitmap {
+    let image = ase.frame(index).image();
+    let width = (image.width() as i32).min(frame_size.x);
+    let height = (image.height() as i32).min(frame_size.y);
+    let mut bitmap = Bitmap::with_size(width as u32, height as u32);
+    for (x, y, pixel) in image.enumerate_pixels() {
+        let [r, g, b, a] = pixel.0;
+        if (x as i32) < width && (y as i32) < height {
+            bitmap.set_pixel(x as i32, y as i32, Pixel::from_rgba(r, g, b, a));
+        }
+    }
+    bitmap
+}
+
+/// Matches tag names against animation types, ignoring case, spaces and underscores.
+pub fn anim_type_from_name(name: &str) -> Option<AnimType> {
+    let normalize = |name: &str| {
+        name.chars()
+            .filter(|c| c.is_alphanumeric())
+            .collect::<String>()
+            .to_lowercase()
+    };
+    let name = normalize(name);
+    AnimType::all().find(|t| normalize(&format!("{t:?}")) == name)
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TagDirection {
+    Forward,
+    Reverse,
+    PingPong,
+}
+
+/// Frames a tag plays through once. Ping-pong does not repeat the ends,
+/// so that it loops smoothly.
+pub fn tag_frames(from: i32, to: i32, direction: TagDirection) -> Vec<i32> {
+    match direction {
+        TagDirection::Forward => (from..=to).collect(),
+        TagDirection::Reverse => (from..=to).rev().collect(),
+        TagDirection::PingPong => (from..=to).chain((from + 1..to).rev()).collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use crate::animations::{delay_from_duration, AnimType};
+    use crate::aseprite::{anim_type_from_name, import_aseprite, tag_frames, TagDirection};
+
+    #[test]
+    fn expands_tags_into_animations() {
+        assert_eq!(tag_frames(2, 4, TagDirection::Forward), vec![2, 3, 4]);
+        assert_eq!(tag_frames(2, 4, TagDirection::Reverse), vec![4, 3, 2]);
+        assert_eq!(
+            tag_frames(2, 5, TagDirection::PingPong),
+            vec![2, 3, 4, 5, 4, 3]
+        );
+        assert_eq!(
+            anim_type_from_name("on press quack"),
+            Some(AnimType::OnPressQuack)
+        );
+        assert_eq!(anim_type_from_name("Flying"), Some(AnimType::Flying));
+        assert_eq!(anim_type_from_name("walk"), None);
+        assert_eq!(delay_from_duration(100), 6);
+    }
+
+    #[test]
+    fn imports_the_example_file() {
+        let imported = import_aseprite(Path::new("Hats/nikotemp.aseprite"), None, None).unwrap();
+        assert_eq!((imported.sheet.width, imported.sheet.height), (32, 32));
+        assert_eq!(imported.frame_size.to_array(), [32, 32]);
+        assert!(imported.animations.is_empty());
+    }
+}
@@ -0,0 +1,184 @@
+use std::{path::Path, process::ExitCode};
+
+use anyhow::{bail, Result};
+use hats_plus_plus_editor::{
+    archive::{load_hat, save_hat},
+    build::build_hat,
+    definition::{apply_definition, read_definition, write_definition},
+    hat_utils::get_metapixels,
+    hats::{AbstractHat, Hat},
+    validation::{has_errors, validate_hat, Finding, Severity},
+};
+
+const USAGE: &str = "usage: hpp <inspect|validate|resave> <hat dir or zip>
+       hpp <export|import> <hat dir or zip> <definition.json or .toml>
+       hpp build <build.toml or build.json>
+resave and import refuse hats with errors, unless given --force";
+
+fn main() -> ExitCode {
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    let force = args.iter().any(|arg| arg == "--force");
+    let args = args
+        .iter()
+        .map(String::as_str)
+        .filter(|arg| *arg != "--force")
+        .collect::<Vec<_>>();
+    let result = match args[..] {
+        ["inspect", dir] => inspect(dir),
+        ["validate", dir] => validate(dir),
+        ["resave", dir] => resave(dir, force),
+        ["export", dir, file] => export(dir, file),
+        ["import", dir, file] => import(dir, file, force),
+        ["build", manifest] => build_hat(Path::new(manifest)).map(|_| ()),
+        _ => {
+            eprintln!("{USAGE}");
+            return ExitCode::from(2);
+        }
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("error: {err:#}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn sorted_elements(hat: &Hat) -> Vec<&dyn AbstractHat> {
+    let mut elements = hat.iter_all_elements().collect::<Vec<_>>();
+    elements.sort_by_key(|e| (e.base().hat_type as i32, e.base().path.clone()));
+    elements
+}
+
+fn element_file_name(element: &dyn AbstractHat) -> String {
+    element
+        .base()
+        .path
+        .as_ref()
+        .and_then(|p| p.file_name())
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+fn inspect(dir: impl AsRef<Path>) -> Result<()> {
+    let (hat, _) = load_hat(dir)?;
+    for element in sorted_elements(&hat) {
+        let base = element.base();
+        println!("{} ({})", base.hat_type, element_file_name(element));
+        println!("  frame size: {}x{}", base.frame_size.x, base.frame_size.y);
+        println!(
+            "  area size: {}x{}",
+            base.hat_area_size.x, base.hat_area_size.y
+        );
+        println!("  frames: {}", element.frames_amount());
+
+        if let Some(bitmap) = &base.bitmap {
+            let metapixels = get_metapixels(bitmap, base.hat_area_size);
+            println!("  metapixels: {}", metapixels.len());
+            for metapixel in metapixels {
+                let Some(pixel_type) = metapixel.get_type() else {
+                    continue;
+                };
+                println!("    {:?} g={} b={}", pixel_type, metapixel.g, metapixel.b);
+            }
+        }
+        for metapixel in &base.unknown_metapixels {
+            println!(
+                "  unknown metapixel: ({}, {}, {})",
+                metapixel.r, metapixel.g, metapixel.b
+            );
+        }
+        for error in &base.metapixel_errors {
+            println!("  error: {error}");
+        }
+
+        if let Some(animations) = element.animations() {
+            println!("  animations: {}", animations.len());
+            for animation in animations {
+                let animation = animation.borrow();
+                let frames = animation
+                    .frames
+                    .iter()
+                    .map(|f| f.value.to_string())
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                println!(
+                    "    {} delay={} looping={} frames=[{}]",
+                    animation.anim_type, animation.delay, animation.looping, frames
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+fn validate(dir: impl AsRef<Path>) -> Result<()> {
+    let (hat, report) = load_hat(dir)?;
+    let mut problems = vec![];
+    for (path, error) in &report.failed_files {
+        problems.push(format!("{}: could not be loaded: {error}", path.display()));
+    }
+    for (path, error) in &report.metapixel_errors {
+        problems.push(format!("{}: {error}", path.display()));
+    }
+
+    if !hat.has_elements() {
+        problems.push("hat has no elements".to_string());
+    }
+    for finding in validate_hat(&hat) {
+        problems.push(describe_finding(&hat, &finding));
+    }
+
+    if problems.is_empty() {
+        println!("ok");
+        return Ok(());
+    }
+    for problem in &problems {
+        println!("{problem}");
+    }
+    bail!("found {} problem(s)", problems.len())
+}
+
+fn describe_finding(hat: &Hat, finding: &Finding) -> String {
+    match finding.element.and_then(|id| hat.element_from_id(id)) {
+        Some(element) => format!(
+            "{}: {}: {}",
+            element_file_name(element),
+            finding.severity,
+            finding.message
+        ),
+        None => format!("{}: {}", finding.severity, finding.message),
+    }
+}
+
+/// Refuses to save a hat with errors, like the editor does, unless forced to.
+fn save_validated(hat: &mut Hat, dir: &Path, force: bool) -> Result<()> {
+    let findings = validate_hat(hat);
+    if has_errors(&findings) && !force {
+        for finding in findings.iter().filter(|f| f.severity == Severity::Error) {
+            println!("{}", describe_finding(hat, finding));
+        }
+        bail!("the hat has errors, use --force to save it anyway");
+    }
+    save_hat(hat, dir)
+}
+
+fn resave(dir: impl AsRef<Path>, force: bool) -> Result<()> {
+    let dir = dir.as_ref();
+    let (mut hat, _) = load_hat(dir)?;
+    save_validated(&mut hat, dir, force)
+}
+
+fn export(dir: impl AsRef<Path>, file: impl AsRef<Path>) -> Result<()> {
+    let (hat, _) = load_hat(dir)?;
+    write_definition(&hat, file)
+}
+
+/// Applies the definition and saves, which writes its values into the metapixels.
+fn import(dir: impl AsRef<Path>, file: impl AsRef<Path>, force: bool) -> Result<()> {
+    let dir = dir.as_ref();
+    let definition = read_definition(file)?;
+    let (mut hat, _) = load_hat(dir)?;
+    apply_definition(&mut hat, &definition)?;
+    save_validated(&mut hat, dir, force)
+}
@@ -0,0 +1,290 @@
+//! Builds a hat from loose frame images and a manifest.
+//!
+//! The manifest sits in the hat folder as `build.toml` or `build.json` and lists
+//! the elements, each with a folder of frames and the settings a definition has.
+//! Frames are packed row by row into a sheet, the order [`AbstractHat::frames_amount`]
+//! and the animation shader count them in, and the hat is saved into the folder
+//! like any other.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use bevy_math::IVec2;
+use pixas::bitmap::Bitmap;
+use serde::{Deserialize, Serialize};
+
+use crate::definition::{
+    apply_definition, AnimationDefinition, DefinitionFormat, ElementDefinition, HatDefinition,
+    PetDefinition, WereableDefinition, WingsDefinition,
+};
+use crate::file_utils::FileStemString;
+use crate::hat_utils::HatNameAndSize;
+use crate::hats::{decode_element, Hat, HatType};
+
+const MANIFEST_NAMES: [&str; 2] = ["build.toml", "build.json"];
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct BuildManifest {
+    pub elements: Vec<BuildElement>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct BuildElement {
+    pub hat_type: HatType,
+    /// File name of the element, pets need different ones. Defaults to the type's.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// Folder of numbered frames, or a single image, relative to the manifest.
+    pub frames: PathBuf,
+    /// Defaults to the size of the first frame.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub frame_size: Option<[i32; 2]>,
+    /// Frames per row of the sheet, all of them fit into one row by default.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub columns: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub wings: Option<WingsDefinition>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pet: Option<PetDefinition>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub wereable: Option<WereableDefinition>,
+    #[serde(default)]
+    pub animations: Vec<AnimationDefinition>,
+}
+
+/// The build manifest of a hat folder, if it has one.
+pub fn find_manifest(dir: &Path) -> Option<PathBuf> {
+    MANIFEST_NAMES
+        .iter()
+        .map(|name| dir.join(name))
+        .find(|path| path.is_file())
+}
+
+pub fn read_manifest(path: &Path) -> Result<BuildManifest> {
+    let text = std::fs::read_to_string(path)?;
+    let manifest = match DefinitionFormat::from_path(path) {
+        DefinitionFormat::Json => serde_json::from_str(&text)?,
+        DefinitionFormat::Toml => toml::from_str(&text)?,
+    };
+    Ok(manifest)
+}
+
+/// Builds the hat the manifest describes and saves it next to the manifest.
+/// Returns the hat as it was saved.
+pub fn build_hat(manifest_path: &Path) -> Result<Hat> {
+    let manifest = read_manifest(manifest_path)
+        .with_context(|| format!("could not read {}", manifest_path.display()))?;
+    let Some(dir) = manifest_path.parent() else {
+        bail!("{} is not in a folder", manifest_path.display());
+    };
+    let mut hat = assemble_hat(&manifest, dir)?;
+    // the files of the last build are replaced, even when their names change
+    if let Ok(previous) = Hat::load(dir) {
+        hat.files = previous.files;
+    }
+    hat.save(dir)?;
+    Hat::load(dir)
+}
+
+/// Packs every element and applies its settings, without saving anything.
+pub fn assemble_hat(manifest: &BuildManifest, dir: &Path) -> Result<Hat> {
+    let mut hat = Hat::new(dir.to_path_buf());
+    let mut definition = HatDefinition { elements: vec![] };
+    for element in &manifest.elements {
+        let frames_path = dir.join(&element.frames);
+        let frames = load_frames(&frames_path)?;
+        let Some(first) = frames.first() else {
+            bail!("{} has no frames", frames_path.display());
+        };
+        let frame_size = element
+            .frame_size
+            .map(IVec2::from_array)
+            .unwrap_or(IVec2::new(first.width as i32, first.height as i32));
+        let columns = element.columns.unwrap_or(frames.len());
+        let sheet = pack_frames(&frames, frame_size, columns)
+            .with_context(|| format!("could not pack {}", frames_path.display()))?;
+        let area_size = IVec2::new(sheet.width as i32, sheet.height as i32);
+
+        let name = element
+            .name
+            .clone()
+            .unwrap_or(element.hat_type.save_name().to_string());
+        let name_and_size = HatNameAndSize::new(name.clone(), Some(area_size));
+        if !name_and_size.is_name_valid(element.hat_type) {
+            bail!("{name} is not a valid name for {}", element.hat_type);
+        }
+        hat.add_boxed_element(decode_element(element.hat_type, sheet, name_and_size)?);
+        definition.elements.push(ElementDefinition {
+            hat_type: element.hat_type,
+            name: Some(name),
+            frame_size: frame_size.to_array(),
+            area_size: area_size.to_array(),
+            wings: element.wings.clone(),
+            pet: element.pet.clone(),
+            wereable: element.wereable.clone(),
+            animations: element.animations.clone(),
+        });
+    }
+    apply_definition(&mut hat, &definition)?;
+    Ok(hat)
+}
+
+/// Reads a single image, or every png in a folder in the order of their numbers.
+pub fn load_frames(path: &Path) -> Result<Vec<Bitmap>> {
+    if path.is_file() {
+        return Ok(vec![Bitmap::from_path(path)?]);
+    }
+    frame_files(path)?
+        .iter()
+        .map(|file| {
+            Bitmap::from_path(file).with_context(|| format!("could not read {}", file.display()))
+        })
+        .collect()
+}
+
+/// Pngs in `dir`, sorted by the number their name ends with, so that
+/// `frame2.png` comes before `frame10.png`.
+pub fn frame_files(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = std::fs::read_dir(dir)?
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.is_file()
+                && path
+                    .extension()
+                    .is_some_and(|e| e.eq_ignore_ascii_case("png"))
+        })
+        .collect::<Vec<_>>();
+    files.sort_by_key(|path| {
+        let stem = path.file_stem_string().unwrap_or_default();
+        let prefix = stem.trim_end_matches(|c: char| c.is_ascii_digit());
+        let number = stem[prefix.len()..].parse::<u64>().ok();
+        (prefix.to_string(), number, stem)
+    });
+    Ok(files)
+}
+
+/// Places frames left to right, `columns` per row. Frames smaller than
+/// `frame_size` keep to the top left corner of their cell.
+pub fn pack_frames(frames: &[Bitmap], frame_size: IVec2, columns: usize) -> Result<Bitmap> {
+    if frame_size.x <= 0 || frame_size.y <= 0 {
+        bail!("frame size {}x{} is empty", frame_size.x, frame_size.y);
+    }
+    let columns = columns.clamp(1, frames.len().max(1));
+    let rows = frames.len().div_ceil(columns);
+    let mut sheet = Bitmap::with_size(
+        (columns as i32 * frame_size.x) as u32,
+        (rows as i32 * frame_size.y) as u32,
+    );
+    for (i, frame) in frames.iter().enumerate() {
+        if frame.width as i32 > frame_size.x || frame.height as i32 > frame_size.y {
+            bail!(
+                "frame {i} is {}x{}, larger than the frame size {}x{}",
+                frame.width,
+                frame.height,
+                frame_size.x,
+                frame_size.y
+            );
+        }
+        let cell = IVec2::new((i % columns) as i32, (i / columns) as i32) * frame_size;
+        for y in 0..frame.height as i32 {
+            for x in 0..frame.width as i32 {
+                if let Some(pixel) = frame.get_pixel(x, y) {
+                    sheet.set_pixel(cell.x + x, cell.y + y, pixel);
+                }
+            }
+        }
+    }
+    Ok(sheet)
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy_math::IVec2;
+    use pixas::bitmap::Bitmap;
+    use pixas::pixel::Pixel;
+
+    use crate::build::{assemble_hat, build_hat, pack_frames, BuildManifest};
+    use crate::hats::{AbstractHat, Hat};
+
+    #[test]
+    fn packs_frames_in_shader_order() {
+        let frames = (0..3u8)
+            .map(|i| {
+                let mut frame = Bitmap::with_size(2, 2);
+                frame.set_pixel(1, 1, Pixel::from_rgb(i * 10, 0, 0));
+                frame
+            })
+            .collect::<Vec<_>>();
+        let sheet = pack_frames(&frames, IVec2::new(2, 2), 2).unwrap();
+        assert_eq!((sheet.width, sheet.height), (4, 4));
+        assert_eq!(sheet.get_pixel(3, 1).unwrap().r, 10);
+        assert_eq!(sheet.get_pixel(1, 3).unwrap().r, 20);
+        assert!(pack_frames(&frames, IVec2::new(1, 1), 2).is_err());
+    }
+
+    #[test]
+    fn assembles_elements_from_the_manifest() {
+        let dir = std::env::temp_dir().join("hpp_assembles_elements");
+        std::fs::remove_dir_all(&dir).ok();
+        std::fs::create_dir_all(dir.join("hat")).unwrap();
+        for i in 0..4 {
+            Bitmap::with_size(32, 32)
+                .save(dir.join("hat").join(format!("{i}.png")))
+                .unwrap();
+        }
+        let manifest: BuildManifest = toml::from_str(
+            r#"
+            [[elements]]
+            hat_type = "Wereable"
+            frames = "hat"
+            columns = 2
+
+            [[elements.animations]]
+            anim_type = "OnPressQuack"
+            delay = 3
+            looping = true
+            frames = [0, 1, 2, 3]
+            "#,
+        )
+        .unwrap();
+
+        let hat = assemble_hat(&manifest, &dir).unwrap();
+        let wereable = hat.wereable().unwrap();
+        assert_eq!(wereable.base.hat_area_size, IVec2::new(64, 64));
+        assert_eq!(wereable.frames_amount(), 4);
+        assert_eq!(wereable.animations[0].borrow().delay, 3);
+        std::fs::remove_dir_all(dir).ok();
+    }
+
+    #[test]
+    fn rebuilding_replaces_the_files_of_the_last_build() {
+        let dir = std::env::temp_dir().join("hpp_rebuilding_replaces_files");
+        std::fs::remove_dir_all(&dir).ok();
+        std::fs::create_dir_all(dir.join("hat")).unwrap();
+        for i in 0..4 {
+            Bitmap::with_size(32, 32)
+                .save(dir.join("hat").join(format!("{i}.png")))
+                .unwrap();
+        }
+        let manifest_path = dir.join("build.toml");
+        for columns in [2, 4] {
+            let manifest = format!(
+                "[[elements]]\nhat_type = \"Wereable\"\nframes = \"hat\"\ncolumns = {columns}\n"
+            );
+            std::fs::write(&manifest_path, manifest).unwrap();
+            build_hat(&manifest_path).unwrap();
+        }
+
+        let hat = Hat::load(&dir).unwrap();
+        let wereable = hat.wereable().unwrap();
+        assert_eq!(wereable.base.hat_area_size, IVec2::new(128, 32));
+        let pngs = std::fs::read_dir(&dir)
+            .unwrap()
+            .flatten()
+            .filter(|entry| entry.path().extension().is_some_and(|e| e == "png"))
+            .count();
+        assert_eq!(pngs, 1);
+        std::fs::remove_dir_all(dir).ok();
+    }
+}
@@ -6,3 +6,5 @@ pub const LIGHT_RED_ACTIVE: Color32 = Color32::from_rgb(217, 89, 89);
 pub const DARK_RED_HOWER: Color32 = Color32::from_rgb(222, 41, 41);
 pub const DARK_RED_INACTIVE: Color32 = Color32::from_rgb(139, 0, 0);
 pub const DARK_RED_ACTIVE: Color32 = Color32::from_rgb(204, 0, 0);
+/// Marks the metapixel columns of an element image.
+pub const METAPIXELS: Color32 = Color32::from_rgb(250, 179, 135);
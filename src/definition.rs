@@ -0,0 +1,351 @@
+//! Hat settings as a readable JSON or TOML file, without the images.
+//!
+//! A definition lists every element with the values the editor otherwise keeps in
+//! metapixels. Applying one onto a hat that has the same elements replaces those
+//! values, and the metapixels are written from them on the next save.
+
+use std::cell::RefCell;
+use std::path::Path;
+use std::rc::Rc;
+
+use anyhow::{bail, Context, Result};
+use bevy_math::IVec2;
+use serde::{Deserialize, Serialize};
+
+use crate::animations::{AnimType, Animation};
+use crate::hats::{
+    AbstractHat, FlyingPet, Hat, HatElementId, HatType, LinkFrameState, PetBase, WalkingPet,
+    Wereable, Wings,
+};
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct HatDefinition {
+    pub elements: Vec<ElementDefinition>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ElementDefinition {
+    pub hat_type: HatType,
+    /// Tells pets apart, other elements are matched by type alone.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    pub frame_size: [i32; 2],
+    /// Has to fit into the element image, the metapixels are written right of it.
+    pub area_size: [i32; 2],
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub wings: Option<WingsDefinition>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pet: Option<PetDefinition>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub wereable: Option<WereableDefinition>,
+    #[serde(default)]
+    pub animations: Vec<AnimationDefinition>,
+}
+
+/// Offsets are stored the way metapixels hold them, 128 is no offset at all.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct WingsDefinition {
+    pub general_offset: [i32; 2],
+    pub crouch_offset: [i32; 2],
+    pub ragdoll_offset: [i32; 2],
+    pub slide_offset: [i32; 2],
+    pub net_offset: [i32; 2],
+    pub gen_animations: bool,
+    pub auto_glide_frame: i32,
+    pub auto_idle_frame: i32,
+    pub auto_anim_speed: i32,
+    pub changes_animations: bool,
+    pub size_state: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PetDefinition {
+    pub distance: i32,
+    pub flipped: bool,
+    pub is_big: bool,
+    pub link_frame_state: LinkFrameState,
+    /// Only flying pets have these two.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub speed: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub changes_angle: Option<bool>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct WereableDefinition {
+    pub strapped_on: bool,
+    pub is_big: bool,
+    pub link_frame_state: LinkFrameState,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub on_spawn_animation: Option<AnimType>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AnimationDefinition {
+    pub anim_type: AnimType,
+    pub delay: i32,
+    pub looping: bool,
+    /// Counted from 0, the editor shows them counted from 1.
+    pub frames: Vec<i32>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DefinitionFormat {
+    Json,
+    Toml,
+}
+
+impl DefinitionFormat {
+    /// Picks the format by extension, anything that is not `.toml` is JSON.
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension() {
+            Some(e) if e.eq_ignore_ascii_case("toml") => Self::Toml,
+            _ => Self::Json,
+        }
+    }
+}
+
+impl HatDefinition {
+    pub fn to_string(&self, format: DefinitionFormat) -> Result<String> {
+        Ok(match format {
+            DefinitionFormat::Json => serde_json::to_string_pretty(self)?,
+            DefinitionFormat::Toml => toml::to_string_pretty(self)?,
+        })
+    }
+
+    pub fn from_str(text: &str, format: DefinitionFormat) -> Result<Self> {
+        Ok(match format {
+            DefinitionFormat::Json => serde_json::from_str(text)?,
+            DefinitionFormat::Toml => toml::from_str(text)?,
+        })
+    }
+}
+
+pub fn write_definition(hat: &Hat, path: impl AsRef<Path>) -> Result<()> {
+    let path = path.as_ref();
+    let text = export_definition(hat).to_string(DefinitionFormat::from_path(path))?;
+    std::fs::write(path, text)?;
+    Ok(())
+}
+
+pub fn read_definition(path: impl AsRef<Path>) -> Result<HatDefinition> {
+    let path = path.as_ref();
+    let text = std::fs::read_to_string(path)?;
+    HatDefinition::from_str(&text, DefinitionFormat::from_path(path))
+        .with_context(|| format!("could not read {}", path.display()))
+}
+
+/// Describes every element of the hat, sorted so that the same hat always
+/// gives the same file.
+pub fn export_definition(hat: &Hat) -> HatDefinition {
+    let mut elements = hat
+        .iter_all_elements()
+        .map(export_element)
+        .collect::<Vec<_>>();
+    elements.sort_by(|a, b| (a.hat_type as i32, &a.name).cmp(&(b.hat_type as i32, &b.name)));
+    HatDefinition { elements }
+}
+
+fn export_element(element: &dyn AbstractHat) -> ElementDefinition {
+    let base = element.base();
+    let wings = element.downcast_ref::<Wings>().map(|w| WingsDefinition {
+        general_offset: w.general_offset.to_array(),
+        crouch_offset: w.crouch_offset.to_array(),
+        ragdoll_offset: w.ragdoll_offset.to_array(),
+        slide_offset: w.slide_offset.to_array(),
+        net_offset: w.net_offset.to_array(),
+        gen_animations: w.gen_animations,
+        auto_glide_frame: w.auto_glide_frame,
+        auto_idle_frame: w.auto_idle_frame,
+        auto_anim_speed: w.auto_anim_speed,
+        changes_animations: w.changes_animations,
+        size_state: w.size_state,
+    });
+    let pet = match element.downcast_ref::<FlyingPet>() {
+        Some(pet) => Some(PetDefinition {
+            speed: Some(pet.speed),
+            changes_angle: Some(pet.changes_angle),
+            ..export_pet(&pet.pet_base)
+        }),
+        None => element
+            .downcast_ref::<WalkingPet>()
+            .map(|pet| export_pet(&pet.pet_base)),
+    };
+    let wereable = element
+        .downcast_ref::<Wereable>()
+        .map(|w| WereableDefinition {
+            strapped_on: w.strapped_on,
+            is_big: w.is_big,
+            link_frame_state: w.link_frame_state,
+            on_spawn_animation: w.on_spawn_animation,
+        });
+    let animations = element
+        .animations()
+        .into_iter()
+        .flatten()
+        .map(|animation| {
+            let animation = animation.borrow();
+            AnimationDefinition {
+                anim_type: animation.anim_type,
+                delay: animation.delay,
+                looping: animation.looping,
+                frames: animation.frames.iter().map(|f| f.value).collect(),
+            }
+        })
+        .collect();
+    ElementDefinition {
+        hat_type: base.hat_type,
+        name: base.name.clone(),
+        frame_size: base.frame_size.to_array(),
+        area_size: base.hat_area_size.to_array(),
+        wings,
+        pet,
+        wereable,
+        animations,
+    }
+}
+
+fn export_pet(pet_base: &PetBase) -> PetDefinition {
+    PetDefinition {
+        distance: pet_base.distance,
+        flipped: pet_base.flipped,
+        is_big: pet_base.is_big,
+        link_frame_state: pet_base.link_frame_state,
+        speed: None,
+        changes_angle: None,
+    }
+}
+
+/// Replaces the settings of the hat's elements with the ones in the definition.
+/// Nothing is changed unless every described element exists in the hat and its
+/// area fits into the element image.
+pub fn apply_definition(hat: &mut Hat, definition: &HatDefinition) -> Result<()> {
+    for element in &definition.elements {
+        let Some(id) = find_element(hat, element) else {
+            match &element.name {
+                Some(name) => bail!("hat has no {} named {name}", element.hat_type),
+                None => bail!("hat has no {}", element.hat_type),
+            }
+        };
+        let image_size = hat.element_from_id(id).unwrap().base().image_size();
+        let area_size = IVec2::from_array(element.area_size);
+        if area_size.x > image_size.x || area_size.y > image_size.y {
+            bail!(
+                "the {} area {}x{} does not fit its {}x{} image",
+                element.hat_type,
+                area_size.x,
+                area_size.y,
+                image_size.x,
+                image_size.y
+            );
+        }
+    }
+    for element in &definition.elements {
+        let id = find_element(hat, element).unwrap();
+        apply_element(hat.element_from_id_mut(id).unwrap(), element);
+    }
+    Ok(())
+}
+
+fn find_element(hat: &Hat, element: &ElementDefinition) -> Option<HatElementId> {
+    let is_pet = matches!(element.hat_type, HatType::WalkingPet | HatType::FlyingPet);
+    hat.iter_all_elements()
+        .find(|e| {
+            e.base().hat_type == element.hat_type && (!is_pet || e.base().name == element.name)
+        })
+        .map(|e| e.id())
+}
+
+fn apply_element(target: &mut dyn AbstractHat, element: &ElementDefinition) {
+    let base = target.base_mut();
+    base.frame_size = IVec2::from_array(element.frame_size);
+    base.hat_area_size = IVec2::from_array(element.area_size);
+
+    if let (Some(wings), Some(definition)) = (target.downcast_mut::<Wings>(), &element.wings) {
+        wings.general_offset = IVec2::from_array(definition.general_offset);
+        wings.crouch_offset = IVec2::from_array(definition.crouch_offset);
+        wings.ragdoll_offset = IVec2::from_array(definition.ragdoll_offset);
+        wings.slide_offset = IVec2::from_array(definition.slide_offset);
+        wings.net_offset = IVec2::from_array(definition.net_offset);
+        wings.gen_animations = definition.gen_animations;
+        wings.auto_glide_frame = definition.auto_glide_frame;
+        wings.auto_idle_frame = definition.auto_idle_frame;
+        wings.auto_anim_speed = definition.auto_anim_speed;
+        wings.changes_animations = definition.changes_animations;
+        wings.size_state = definition.size_state;
+    }
+    if let Some(definition) = &element.pet {
+        if let Some(pet) = target.downcast_mut::<FlyingPet>() {
+            apply_pet(&mut pet.pet_base, definition);
+            pet.speed = definition.speed.unwrap_or(pet.speed);
+            pet.changes_angle = definition.changes_angle.unwrap_or(pet.changes_angle);
+        } else if let Some(pet) = target.downcast_mut::<WalkingPet>() {
+            apply_pet(&mut pet.pet_base, definition);
+        }
+    }
+    if let (Some(wereable), Some(definition)) =
+        (target.downcast_mut::<Wereable>(), &element.wereable)
+    {
+        wereable.strapped_on = definition.strapped_on;
+        wereable.is_big = definition.is_big;
+        wereable.link_frame_state = definition.link_frame_state;
+        wereable.on_spawn_animation = definition.on_spawn_animation;
+    }
+
+    if let Some(animations) = target.animations_mut() {
+        *animations = element
+            .animations
+            .iter()
+            .map(|a| {
+                let frames = a.frames.iter().map(|&f| f.into()).collect();
+                let animation = Animation::new(a.anim_type, a.delay, a.looping, frames);
+                Rc::new(RefCell::new(animation))
+            })
+            .collect();
+    }
+}
+
+fn apply_pet(pet_base: &mut PetBase, definition: &PetDefinition) {
+    pet_base.distance = definition.distance;
+    pet_base.flipped = definition.flipped;
+    pet_base.is_big = definition.is_big;
+    pet_base.link_frame_state = definition.link_frame_state;
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::definition::{apply_definition, export_definition, DefinitionFormat, HatDefinition};
+    use crate::hats::Hat;
+
+    #[test]
+    fn round_trips_and_applies_definitions() {
+        let mut hat = Hat::load("Hats/ChaosFridge").unwrap();
+        let definition = export_definition(&hat);
+        assert!(!definition.elements.is_empty());
+        for format in [DefinitionFormat::Json, DefinitionFormat::Toml] {
+            let text = definition.to_string(format).unwrap();
+            assert_eq!(HatDefinition::from_str(&text, format).unwrap(), definition);
+        }
+
+        let mut changed = definition.clone();
+        let element = changed
+            .elements
+            .iter_mut()
+            .find(|e| !e.animations.is_empty())
+            .unwrap();
+        element.animations[0].delay += 1;
+        apply_definition(&mut hat, &changed).unwrap();
+        assert_eq!(export_definition(&hat), changed);
+
+        assert!(apply_definition(&mut Hat::default(), &changed).is_err());
+    }
+
+    #[test]
+    fn refuses_areas_larger_than_the_image() {
+        let mut hat = Hat::load("Hats/ChaosFridge").unwrap();
+        let mut definition = export_definition(&hat);
+        definition.elements[0].area_size[0] += 10_000;
+        assert!(apply_definition(&mut hat, &definition).is_err());
+        assert_ne!(export_definition(&hat), definition);
+    }
+}
@@ -0,0 +1,145 @@
+//! New elements from an animated GIF or a folder of numbered frames, packed
+//! into a sheet with an animation that plays through all of them.
+
+use std::cell::RefCell;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use std::rc::Rc;
+
+use anyhow::{bail, Result};
+use bevy_math::IVec2;
+use image::codecs::gif::GifDecoder;
+use image::AnimationDecoder;
+use pixas::bitmap::Bitmap;
+use pixas::pixel::Pixel;
+
+use crate::animations::{avalible_animations, delay_from_duration, AnimType, Animation};
+use crate::archive::encode_png;
+use crate::build::{load_frames, pack_frames};
+use crate::file_utils::FileStemString;
+use crate::hat_utils::HatNameAndSize;
+use crate::hats::{AbstractHat, LoadHat};
+
+/// Delay of animations made from frames that do not say how long they last.
+pub const DEFAULT_FRAMES_DELAY: i32 = 3;
+
+#[derive(Debug)]
+pub struct ImportedFrames {
+    pub frames: Vec<Bitmap>,
+    /// Average frame delay of a GIF, in game ticks.
+    pub delay: Option<i32>,
+}
+
+pub fn is_gif(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case("gif"))
+}
+
+/// Reads the frames of a GIF, or the numbered pngs in a folder.
+pub fn read_frames(path: &Path) -> Result<ImportedFrames> {
+    if !is_gif(path) {
+        return Ok(ImportedFrames {
+            frames: load_frames(path)?,
+            delay: None,
+        });
+    }
+    let decoder = GifDecoder::new(BufReader::new(File::open(path)?))?;
+    let mut frames = vec![];
+    let mut total_duration = 0;
+    for frame in decoder.into_frames().collect_frames()? {
+        let (numerator, denominator) = frame.delay().numer_denom_ms();
+        total_duration += numerator / denominator.max(1);
+        let image = frame.into_buffer();
+        let mut bitmap = Bitmap::with_size(image.width(), image.height());
+        for (x, y, pixel) in image.enumerate_pixels() {
+            let [r, g, b, a] = pixel.0;
+            bitmap.set_pixel(x as i32, y as i32, Pixel::from_rgba(r, g, b, a));
+        }
+        frames.push(bitmap);
+    }
+    let delay = (!frames.is_empty())
+        .then(|| delay_from_duration(total_duration / frames.len() as u32).max(1));
+    Ok(ImportedFrames { frames, delay })
+}
+
+/// Packs the frames at `path` into one row and makes an element of them with an
+/// animation over every frame. The animation is `anim_type` if the element can
+/// have it, its first kind of animation otherwise.
+pub fn import_frames<T: LoadHat>(path: &Path, anim_type: AnimType) -> Result<T> {
+    let imported = read_frames(path)?;
+    if imported.frames.is_empty() {
+        bail!("{} has no frames", path.display());
+    }
+    let frame_size = imported.frames.iter().fold(IVec2::ZERO, |size, frame| {
+        size.max(IVec2::new(frame.width as i32, frame.height as i32))
+    });
+    let frames_amount = imported.frames.len();
+    let sheet = pack_frames(&imported.frames, frame_size, frames_amount)?;
+    let size = IVec2::new(sheet.width as i32, sheet.height as i32);
+    let name = path.file_stem_string().unwrap_or_default();
+    let mut element = T::from_bitmap(sheet, HatNameAndSize::new(name, Some(size)))?;
+    element.base_mut().frame_size = frame_size;
+
+    let allowed = avalible_animations(element.base().hat_type).unwrap_or_default();
+    let anim_type = match allowed.contains(&anim_type) {
+        true => Some(anim_type),
+        false => allowed.first().copied(),
+    };
+    if let (Some(anim_type), Some(animations)) = (anim_type, element.animations_mut()) {
+        let frames = (0..frames_amount as i32).map(Into::into).collect();
+        let delay = imported.delay.unwrap_or(DEFAULT_FRAMES_DELAY);
+        let animation = Animation::new(anim_type, delay, true, frames);
+        animations.push(Rc::new(RefCell::new(animation)));
+    }
+    Ok(element)
+}
+
+/// Writes the element image into `dir`, named the way the element is saved
+/// without metapixels, and points the element at the file.
+pub fn write_sheet(element: &mut dyn AbstractHat, dir: &Path) -> Result<()> {
+    let base = element.base_mut();
+    let Some(bitmap) = &base.bitmap else {
+        bail!("{} has no image", base.hat_type);
+    };
+    let name = base
+        .name
+        .clone()
+        .unwrap_or(base.hat_type.save_name().to_string());
+    let path = dir.join(format!("{name}.png"));
+    std::fs::write(&path, encode_png(bitmap)?)?;
+    base.path = Some(path);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use pixas::bitmap::Bitmap;
+
+    use crate::animations::AnimType;
+    use crate::frame_import::{import_frames, DEFAULT_FRAMES_DELAY};
+    use crate::hats::{AbstractHat, Wings};
+
+    #[test]
+    fn imports_numbered_frames() {
+        let dir = std::env::temp_dir().join("hpp_imports_numbered_frames");
+        std::fs::remove_dir_all(&dir).ok();
+        std::fs::create_dir_all(&dir).unwrap();
+        for i in [1, 2, 10] {
+            Bitmap::with_size(32, 30 + i)
+                .save(dir.join(format!("frame{i}.png")))
+                .unwrap();
+        }
+
+        // wings have no OnDefault animation, so they get their first kind instead
+        let wings = import_frames::<Wings>(&dir, AnimType::OnDefault).unwrap();
+        assert_eq!(wings.base.frame_size.to_array(), [32, 40]);
+        assert_eq!(wings.base.hat_area_size.to_array(), [96, 40]);
+        assert_eq!(wings.frames_amount(), 3);
+        let animation = wings.animations[0].borrow();
+        assert_eq!(animation.anim_type, AnimType::Flying);
+        assert_eq!(animation.delay, DEFAULT_FRAMES_DELAY);
+        assert_eq!(animation.frames.len(), 3);
+        std::fs::remove_dir_all(dir).ok();
+    }
+}
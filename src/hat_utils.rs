@@ -1,11 +1,11 @@
-use anyhow::Result;
 use bevy_math::IVec2;
 use num_traits::FromPrimitive;
-use pixas::bitmap::{Bitmap, BitmapLoadError};
-use std::path::Path;
+use pixas::bitmap::Bitmap;
 
 use crate::animations::AnimType;
 use crate::frames_from_range::frames_from_range;
+use crate::metapixel_schema::OFFSET_BIAS;
+use crate::metapixels::{MetapixelErrorReason, MetapixelParseError};
 use crate::prelude::*;
 
 const SEPARATOR: &str = "_";
@@ -20,10 +20,10 @@ impl HatNameAndSize {
     pub fn new(name: String, size: Option<IVec2>) -> Self {
         Self { name, size }
     }
-    pub fn is_name_valid(&self) -> bool {
-        return (0..(HatType::Unspecified as i32))
-            .map(|i| HatType::from_i32(i).unwrap())
-            .any(|t| t.save_name() == self.name.to_lowercase());
+    /// Whether the name would be loaded back as `hat_type`. Pets may carry a suffix,
+    /// like `flyingpet1`, the other elements have to match exactly.
+    pub fn is_name_valid(&self, hat_type: HatType) -> bool {
+        HatType::from_file_name(&self.name) == Some(hat_type)
     }
 }
 
@@ -37,27 +37,30 @@ pub fn get_animation(metapixels: &[Metapixel], index: usize) -> Option<Animation
         return None;
     };
 
-    let (MetapixelType::AnimationType, MetapixelType::AnimationDelay, MetapixelType::AnimationLoop) =
-        (anim_type.get_type(), delay.get_type(), looping.get_type())
+    let (
+        Some(MetapixelType::AnimationType),
+        Some(MetapixelType::AnimationDelay),
+        Some(MetapixelType::AnimationLoop),
+    ) = (anim_type.get_type(), delay.get_type(), looping.get_type())
     else {
         return None;
     };
 
     match frame_or_range.get_type() {
-        MetapixelType::AnimationFrame | MetapixelType::AnimationFramePeriod => {}
+        Some(MetapixelType::AnimationFrame | MetapixelType::AnimationFramePeriod) => {}
         _ => return None,
     };
     let anim_type = AnimType::from_u8(anim_type.g)?;
-    let looping = looping.g.max(1) == 0;
+    let looping = looping.g != 0;
 
-    if let MetapixelType::AnimationFramePeriod = frame_or_range.get_type() {
+    if let Some(MetapixelType::AnimationFramePeriod) = frame_or_range.get_type() {
         let frames = frames_from_range(frame_or_range.g as i32, frame_or_range.b as i32);
         return Some(Animation::new(anim_type, delay.g as i32, looping, frames));
     }
 
     let frames = &metapixels[(index + 3)..]
         .iter()
-        .take_while(|m| matches!(m.get_type(), MetapixelType::AnimationFrame))
+        .take_while(|m| matches!(m.get_type(), Some(MetapixelType::AnimationFrame)))
         .map(|m| (m.g as i32).into())
         .collect::<Vec<_>>();
 
@@ -94,20 +97,56 @@ pub fn get_name_and_size(name: &str) -> HatNameAndSize {
         size: Some(IVec2::new(size_x, size_y)),
     }
 }
+/// Metapixels read from the column next to the hat area. Pixels that could
+/// not be understood end up in `errors` instead of being dropped silently.
+#[derive(Debug, Default)]
+pub struct DecodedMetapixels {
+    pub metapixels: Vec<Metapixel>,
+    /// Position of each metapixel, relative to the start of the column.
+    pub positions: Vec<IVec2>,
+    /// Pixels with a type this version does not know, kept so they can be saved back.
+    pub unknown: Vec<Metapixel>,
+    pub errors: Vec<MetapixelParseError>,
+}
+
+impl DecodedMetapixels {
+    fn push_error(&mut self, index: usize, reason: MetapixelErrorReason) {
+        let (pixel, position) = (self.metapixels[index], self.positions[index]);
+        self.errors.push(parse_error(position, pixel, reason));
+    }
+}
+
+fn parse_error(
+    position: IVec2,
+    pixel: Metapixel,
+    reason: MetapixelErrorReason,
+) -> MetapixelParseError {
+    MetapixelParseError {
+        x: position.x,
+        y: position.y,
+        r: pixel.r,
+        g: pixel.g,
+        b: pixel.b,
+        reason,
+    }
+}
+
 pub fn get_metapixels_and_size(
-    path: &Path,
+    bitmap: &Bitmap,
     name_and_size: &HatNameAndSize,
-) -> Result<(Vec<Metapixel>, IVec2), BitmapLoadError> {
-    let bitmap = Bitmap::from_path(path)?;
+) -> (DecodedMetapixels, IVec2) {
     let size = name_and_size
         .size
         .unwrap_or(IVec2::new(bitmap.width as i32, bitmap.height as i32));
-    let metapixels = get_metapixels(&bitmap, size);
-    Ok((metapixels, size))
+    let metapixels = decode_metapixels(bitmap, size);
+    (metapixels, size)
+}
+pub fn get_metapixels(bitmap: &Bitmap, size: IVec2) -> Vec<Metapixel> {
+    decode_metapixels(bitmap, size).metapixels
 }
-fn get_metapixels(bitmap: &Bitmap, size: IVec2) -> Vec<Metapixel> {
+pub fn decode_metapixels(bitmap: &Bitmap, size: IVec2) -> DecodedMetapixels {
     let metapixels_size = IVec2::new(bitmap.width as i32 - size.x, bitmap.height as i32);
-    let mut metapixels: Vec<Metapixel> = vec![];
+    let mut decoded = DecodedMetapixels::default();
 
     for x in 0..=metapixels_size.x {
         for y in 0..=metapixels_size.y {
@@ -115,16 +154,218 @@ fn get_metapixels(bitmap: &Bitmap, size: IVec2) -> Vec<Metapixel> {
                 continue;
             };
 
-            if pixel.is_empty() {
+            // Fully transparent pixels carry no data, whatever their color is.
+            if pixel.a == 0 {
                 continue;
             }
-            let Some(metapixel) = Metapixel::new(pixel.r, pixel.g, pixel.b) else {
-                continue;
+            let metapixel = Metapixel {
+                r: pixel.r,
+                g: pixel.g,
+                b: pixel.b,
             };
+            if metapixel.get_type().is_none() {
+                let error = parse_error(
+                    IVec2::new(x, y),
+                    metapixel,
+                    MetapixelErrorReason::UnknownType,
+                );
+                decoded.errors.push(error);
+                decoded.unknown.push(metapixel);
+                continue;
+            }
 
-            metapixels.push(metapixel);
+            decoded.metapixels.push(metapixel);
+            decoded.positions.push(IVec2::new(x, y));
         }
     }
 
+    check_animations(&mut decoded);
+    decoded.errors.sort_by_key(|e| (e.x, e.y));
+    decoded
+}
+
+/// What every metapixel means, like `AnimationDelay 4 for OnPressQuack`. Frames
+/// count from one, the way the editor lists them, and wing offsets from 128.
+pub fn describe_metapixels(metapixels: &[Metapixel]) -> Vec<String> {
+    use MetapixelType as T;
+    let mut animation = None;
     metapixels
+        .iter()
+        .map(|pixel| {
+            let Some(pixel_type) = pixel.get_type() else {
+                return format!("Unknown type {}", pixel.r);
+            };
+            let (g, b) = (pixel.g as i32, pixel.b as i32);
+            let is_animation_data = matches!(
+                pixel_type,
+                T::AnimationDelay | T::AnimationLoop | T::AnimationFrame | T::AnimationFramePeriod
+            );
+            if !is_animation_data {
+                animation = None;
+            }
+            let owner = match animation {
+                Some(anim_type) => format!("{anim_type:?}"),
+                None => "no animation".to_string(),
+            };
+            match pixel_type {
+                T::AnimationType | T::OnSpawnAnimation => {
+                    let anim_type = AnimType::from_u8(pixel.g);
+                    if matches!(pixel_type, T::AnimationType) {
+                        animation = anim_type;
+                    }
+                    match anim_type {
+                        Some(anim_type) => format!("{pixel_type:?} {anim_type:?}"),
+                        None => format!("{pixel_type:?} unknown ({g})"),
+                    }
+                }
+                T::AnimationDelay => format!("AnimationDelay {g} for {owner}"),
+                T::AnimationLoop => format!("AnimationLoop {} for {owner}", g != 0),
+                T::AnimationFrame => format!("AnimationFrame {} for {owner}", g + 1),
+                T::AnimationFramePeriod => {
+                    format!("AnimationFramePeriod {}-{} for {owner}", g + 1, b + 1)
+                }
+                T::FrameSize => format!("FrameSize {g}x{b}"),
+                T::LinkFrameState => {
+                    format!("LinkFrameState {:?}", LinkFrameState::from(pixel.g))
+                }
+                T::WingsGeneralOffset
+                | T::WingsCrouchOffset
+                | T::WingsRagdollOffset
+                | T::WingsSlideOffset
+                | T::WingsNetOffset => {
+                    format!("{pixel_type:?} {}, {}", g - OFFSET_BIAS, b - OFFSET_BIAS)
+                }
+                T::WingsAutoGlideFrame | T::WingsAutoIdleFrame => {
+                    format!("{pixel_type:?} {}", g + 1)
+                }
+                T::PetDistance | T::PetSpeed | T::WingsAutoAnimationsSpeed => {
+                    format!("{pixel_type:?} {g}")
+                }
+                T::StrappedOn
+                | T::IsBigHat
+                | T::GenerateWingsAnimations
+                | T::PetChangesAngle
+                | T::PetNoFlip
+                | T::ChangeAnimationsEveryLevel => format!("{pixel_type:?}"),
+            }
+        })
+        .collect()
+}
+
+/// Every animation has to start with AnimationType, AnimationDelay and AnimationLoop,
+/// followed by either a frame period or a run of frames.
+fn check_animations(decoded: &mut DecodedMetapixels) {
+    let mut in_frames = false;
+    let mut i = 0;
+    while i < decoded.metapixels.len() {
+        let pixel_type = decoded.metapixels[i].get_type();
+        match pixel_type {
+            Some(MetapixelType::AnimationType) => {
+                if AnimType::from_u8(decoded.metapixels[i].g).is_none() {
+                    decoded.push_error(i, MetapixelErrorReason::UnknownAnimationType);
+                }
+                let types = (1..=3)
+                    .map(|offset| {
+                        decoded
+                            .metapixels
+                            .get(i + offset)
+                            .and_then(|m| m.get_type())
+                    })
+                    .collect::<Vec<_>>();
+                let has_header = matches!(
+                    types[..2],
+                    [
+                        Some(MetapixelType::AnimationDelay),
+                        Some(MetapixelType::AnimationLoop)
+                    ]
+                );
+                let has_frames = matches!(
+                    types[2],
+                    Some(MetapixelType::AnimationFrame | MetapixelType::AnimationFramePeriod)
+                );
+                if !has_header || !has_frames {
+                    decoded.push_error(i, MetapixelErrorReason::TruncatedAnimation);
+                    in_frames = false;
+                    i += 1;
+                    continue;
+                }
+                in_frames = matches!(types[2], Some(MetapixelType::AnimationFrame));
+                i += 4;
+                continue;
+            }
+            Some(MetapixelType::AnimationFrame) if in_frames => {}
+            Some(
+                MetapixelType::AnimationFrame
+                | MetapixelType::AnimationFramePeriod
+                | MetapixelType::AnimationDelay
+                | MetapixelType::AnimationLoop,
+            ) => {
+                decoded.push_error(i, MetapixelErrorReason::FrameBeforeAnimationType);
+                in_frames = false;
+            }
+            _ => in_frames = false,
+        }
+        i += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy_math::IVec2;
+    use pixas::{bitmap::Bitmap, pixel::Pixel};
+
+    use crate::hat_utils::{decode_metapixels, describe_metapixels};
+    use crate::metapixels::{metapixels, MetapixelErrorReason, MetapixelType};
+
+    #[test]
+    fn reports_broken_metapixels_with_positions() {
+        let mut bitmap = Bitmap::with_size(2, 4);
+        bitmap.set_pixel(1, 0, Pixel::from_rgb(3, 0, 0));
+        bitmap.set_pixel(1, 1, Pixel::from_rgb(4, 5, 0));
+        bitmap.set_pixel(1, 2, Pixel::from_rgb(200, 1, 2));
+        bitmap.set_pixel(1, 3, Pixel::from_rgb(6, 0, 0));
+
+        let decoded = decode_metapixels(&bitmap, IVec2::new(1, 4));
+        let errors = decoded
+            .errors
+            .iter()
+            .map(|e| (e.x, e.y, e.reason))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            errors,
+            [
+                (0, 0, MetapixelErrorReason::TruncatedAnimation),
+                (0, 1, MetapixelErrorReason::FrameBeforeAnimationType),
+                (0, 2, MetapixelErrorReason::UnknownType),
+                (0, 3, MetapixelErrorReason::FrameBeforeAnimationType),
+            ]
+        );
+        assert_eq!(decoded.errors[2].r, 200);
+        assert_eq!(decoded.metapixels.len(), 3);
+    }
+
+    #[test]
+    fn describes_metapixels_with_their_animation() {
+        let pixels = metapixels!(
+            (MetapixelType::FrameSize, 32, 32),
+            (MetapixelType::AnimationType, 1, 0),
+            (MetapixelType::AnimationDelay, 4, 0),
+            (MetapixelType::AnimationLoop, 1, 0),
+            (MetapixelType::AnimationFramePeriod, 0, 5),
+            (MetapixelType::WingsCrouchOffset, 130, 127),
+            (MetapixelType::AnimationFrame, 2, 0),
+        );
+        assert_eq!(
+            describe_metapixels(&pixels),
+            [
+                "FrameSize 32x32",
+                "AnimationType OnPressQuack",
+                "AnimationDelay 4 for OnPressQuack",
+                "AnimationLoop true for OnPressQuack",
+                "AnimationFramePeriod 1-6 for OnPressQuack",
+                "WingsCrouchOffset 2, -1",
+                "AnimationFrame 3 for no animation",
+            ]
+        );
+    }
 }
@@ -2,6 +2,7 @@ use core::panic;
 use std::cell::{Cell, RefCell};
 use std::collections::HashMap;
 use std::fmt::Display;
+use std::hash::{Hash, Hasher};
 use std::ops::ControlFlow;
 use std::path::{Path, PathBuf};
 use std::rc::Rc;
@@ -10,7 +11,8 @@ use crate::animations::{AnimType, Animation};
 use crate::file_utils::FileStemString;
 use crate::frames_from_range::frames_from_range;
 use crate::hat_utils::*;
-use crate::metapixels::Metapixels;
+use crate::metapixel_schema::{read_metapixels, write_metapixels};
+use crate::metapixels::MetapixelParseError;
 use crate::prelude::*;
 use crate::texture::Texture;
 use crate::texture_reloader::TextureReloader;
@@ -20,10 +22,10 @@ use bevy_math::IVec2;
 use derivative::Derivative;
 use downcast_rs::{impl_downcast, Downcast};
 use eframe::glow::Context;
-use num_traits::FromPrimitive;
 use pixas::bitmap::Bitmap;
 use pixas::pixel::Pixel;
 use pixas::Rectanlge;
+use serde::{Deserialize, Serialize};
 
 macro_rules! impl_abstract_hat {
     ($t:ty, $base_name:ident, $($anims_name:ident).+) => {
@@ -44,8 +46,13 @@ macro_rules! impl_abstract_hat {
                 Some(&self.$($anims_name).+)
             }
             fn frames_amount(&self) -> u32 {
-                let frames_x = (self.texture().map(|t| t.width()).unwrap_or(0)) / self.base().frame_size.x;
-                let frames_y = (self.texture().map(|t| t.height()).unwrap_or(0)) / self.base().frame_size.y;
+                let frame_size = self.base().frame_size;
+                if frame_size.x <= 0 || frame_size.y <= 0 {
+                    return 0;
+                }
+                let image_size = self.base().image_size();
+                let frames_x = image_size.x / frame_size.x;
+                let frames_y = image_size.y / frame_size.y;
                 (frames_x * frames_y) as u32
             }
             fn animations_mut(&mut self) -> Option<&mut Vec<AnimationCell>> {
@@ -54,6 +61,16 @@ macro_rules! impl_abstract_hat {
             fn id(&self) -> HatElementId {
                 self.base().id
             }
+            fn hash_state(&self, mut state: &mut dyn Hasher) {
+                self.hash(&mut state);
+            }
+            fn clone_boxed(&self) -> Box<dyn AbstractHat> {
+                let mut clone = self.clone();
+                for animation in &mut clone.$($anims_name).+ {
+                    *animation = Rc::new(RefCell::new(animation.borrow().clone()));
+                }
+                Box::new(clone)
+            }
         }
     };
     ($t:ty, $base_name:ident) => {
@@ -74,8 +91,13 @@ macro_rules! impl_abstract_hat {
                 None
             }
             fn frames_amount(&self) -> u32 {
-                let frames_x = (self.texture().map(|t| t.width()).unwrap_or(0)) / self.base().frame_size.x;
-                let frames_y = (self.texture().map(|t| t.height()).unwrap_or(0)) / self.base().frame_size.y;
+                let frame_size = self.base().frame_size;
+                if frame_size.x <= 0 || frame_size.y <= 0 {
+                    return 0;
+                }
+                let image_size = self.base().image_size();
+                let frames_x = image_size.x / frame_size.x;
+                let frames_y = image_size.y / frame_size.y;
                 (frames_x * frames_y) as u32
             }
             fn animations_mut(&mut self) -> Option<&mut Vec<AnimationCell>> {
@@ -84,6 +106,12 @@ macro_rules! impl_abstract_hat {
             fn id(&self) -> HatElementId {
                 self.base().id
             }
+            fn hash_state(&self, mut state: &mut dyn Hasher) {
+                self.hash(&mut state);
+            }
+            fn clone_boxed(&self) -> Box<dyn AbstractHat> {
+                Box::new(self.clone())
+            }
         }
     };
 }
@@ -99,7 +127,6 @@ impl_abstract_hat!(FlyingPet, hat_base, pet_base.animations);
 const PREVIEW_NAME: &str = "preview";
 const EXTRA_NAME: &str = "extrahat";
 const WEREABLE_NAME: &str = "hat";
-#[allow(dead_code)]
 const ROOM_NAME: &str = "room";
 const WALKING_PET_NAME: &str = "walkingpet";
 const FLYING_PET_NAME: &str = "flyingpet";
@@ -113,6 +140,9 @@ pub const DEFAULT_AUTO_SPEED: i32 = 4;
 pub const MAX_EXTRA_HAT_SIZE: IVec2 = IVec2::new(97, 56);
 pub const MIN_FRAME_SIZE: i32 = 32;
 pub const MAX_FRAME_SIZE: i32 = 64;
+const SAVE_TEMP_DIR: &str = ".hpp_save";
+const BACKUP_DIR: &str = ".bak";
+const BACKUPS_KEPT: usize = 3;
 
 thread_local! {
     static HAT_ID_COUNTER: Cell<u32> = const { Cell::new(0) };
@@ -126,6 +156,12 @@ pub fn hat_id() -> HatElementId {
 
 type AnimationCell = Rc<RefCell<Animation>>;
 
+fn hash_animations<H: Hasher>(animations: &[AnimationCell], state: &mut H) {
+    for animation in animations {
+        animation.borrow().hash(state);
+    }
+}
+
 #[derive(Clone, Copy, Debug, Hash, PartialEq, Eq, Default)]
 pub struct HatElementId(u32);
 
@@ -139,6 +175,10 @@ pub trait AbstractHat: Downcast + std::fmt::Debug {
     fn animations_mut(&mut self) -> Option<&mut Vec<AnimationCell>>;
     fn frames_amount(&self) -> u32;
     fn id(&self) -> HatElementId;
+    /// Feeds everything the user can edit into `state`, images aside.
+    fn hash_state(&self, state: &mut dyn Hasher);
+    /// Copies the element with its own animations, so editing the copy leaves this one alone.
+    fn clone_boxed(&self) -> Box<dyn AbstractHat>;
 }
 
 pub trait GetHatBase {
@@ -156,36 +196,20 @@ trait GenMetapixels {
     fn gen_metapixels(&self) -> Vec<Metapixel>;
 }
 
-macro_rules! gen_metapixels_branch {
-    ($e: expr, $t:ty) => {
-        if let Some(hat) = $e.downcast_ref::<$t>() {
-            hat.gen_metapixels()
-        } else {
-            panic!("the type of the hat does not match its actual type (wierd, I know)")
-        }
-    };
-}
 impl GenMetapixels for Box<dyn AbstractHat> {
     fn gen_metapixels(&self) -> Vec<Metapixel> {
-        match self.base().hat_type {
-            HatType::Wereable => gen_metapixels_branch!(self, Wereable),
-            HatType::Wings => gen_metapixels_branch!(self, Wings),
-            HatType::Extra => gen_metapixels_branch!(self, Extra),
-            HatType::WalkingPet => gen_metapixels_branch!(self, WalkingPet),
-            HatType::FlyingPet => gen_metapixels_branch!(self, FlyingPet),
-            HatType::Room => gen_metapixels_branch!(self, RoomHat),
-            HatType::Preview => gen_metapixels_branch!(self, Preview),
-            HatType::Unspecified => panic!("Invalid hat type: {}", HatType::Unspecified),
-        }
+        write_metapixels(self.as_ref())
     }
 }
 
 impl SaveHat for Box<dyn AbstractHat> {}
 trait SaveHat: GenMetapixels + GetHatBase {
-    fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+    /// File name and image the element is saved as, metapixels included.
+    fn encode(&self) -> Result<(String, Bitmap)> {
         let base = &self.get_base();
         let area_size = base.hat_area_size;
-        let metapixels = self.gen_metapixels();
+        let mut metapixels = self.gen_metapixels();
+        metapixels.extend_from_slice(&base.unknown_metapixels);
         let save_name = base
             .name
             .as_ref()
@@ -202,8 +226,15 @@ trait SaveHat: GenMetapixels + GetHatBase {
                 bail!("unable to save hat {0}: no bitmap found", file_name);
             }
         };
-        let rows_amount = (metapixels.len() as f32 / hat_bitmap.height as f32).ceil() as i32;
-        let final_image_size = IVec2::new(rows_amount + area_size.x, hat_bitmap.height as i32);
+        let positions = layout_metapixels(&metapixels, hat_bitmap.height as i32);
+        let rows_amount = positions.last().map_or(0, |p| p.x + 1);
+        // without metapixels the area is all there is, so crop the image to it
+        let height = if metapixels.is_empty() {
+            area_size.y
+        } else {
+            hat_bitmap.height as i32
+        };
+        let final_image_size = IVec2::new(rows_amount + area_size.x, height);
         let mut final_image =
             Bitmap::with_size(final_image_size.x as u32, final_image_size.y as u32);
         final_image.draw_from(hat_bitmap, 0, 0);
@@ -217,26 +248,34 @@ trait SaveHat: GenMetapixels + GetHatBase {
             *pixel = Pixel::empty();
             ControlFlow::Continue(())
         });
-        insert_metapixels(&mut final_image, &metapixels, area_size);
-        final_image.save(path.as_ref().join(file_name)).ok();
-        Ok(())
+        insert_metapixels(&mut final_image, &metapixels, &positions, area_size);
+        Ok((file_name, final_image))
     }
 }
 
+/// Decodes an element from its image. Nothing here touches OpenGL,
+/// the editor attaches textures later with [`HatBase::load_texture`].
 pub trait LoadHat: Sized + AbstractHat {
-    fn load_from_path(path: impl AsRef<Path>, gl: &Context) -> Result<Self> {
+    fn load_from_path(path: impl AsRef<Path>) -> Result<Self> {
         let name_and_size =
             get_name_and_size(&path.as_ref().file_stem_string().unwrap_or_default());
-        LoadHat::load_from_name_and_size(path, name_and_size, gl)
+        LoadHat::load_from_name_and_size(path, name_and_size)
     }
     fn load_from_name_and_size(
         path: impl AsRef<Path>,
         name_and_size: HatNameAndSize,
-        gl: &Context,
-    ) -> Result<Self>;
+    ) -> Result<Self> {
+        let bitmap = Bitmap::from_path(path.as_ref())?;
+        let mut hat = Self::from_bitmap(bitmap, name_and_size)?;
+        hat.base_mut().path = Some(path.as_ref().to_owned());
+        Ok(hat)
+    }
+    fn from_bitmap(bitmap: Bitmap, name_and_size: HatNameAndSize) -> Result<Self>;
 }
 
-#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash, FromPrimitive)]
+#[derive(
+    Copy, Clone, Debug, Default, PartialEq, Eq, Hash, FromPrimitive, Serialize, Deserialize,
+)]
 pub enum HatType {
     Wereable,
     Wings,
@@ -278,6 +317,20 @@ impl HatType {
             Self::Unspecified => "".to_string(),
         }
     }
+    /// Element type of a hat file, judging by the name part of its stem.
+    pub fn from_file_name(name: &str) -> Option<HatType> {
+        let name = name.to_lowercase();
+        match name.as_str() {
+            ROOM_NAME => Some(Self::Room),
+            EXTRA_NAME => Some(Self::Extra),
+            WINGS_NAME => Some(Self::Wings),
+            _ if name.contains(FLYING_PET_NAME) => Some(Self::FlyingPet),
+            _ if name.contains(WALKING_PET_NAME) => Some(Self::WalkingPet),
+            PREVIEW_NAME => Some(Self::Preview),
+            WEREABLE_NAME => Some(Self::Wereable),
+            _ => None,
+        }
+    }
     pub fn save_name(&self) -> &str {
         match self {
             Self::Wereable => "hat",
@@ -292,13 +345,22 @@ impl HatType {
     }
 }
 
-#[derive(Copy, Clone, Default, PartialEq, Eq, Debug)]
+#[derive(Copy, Clone, Default, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
 pub enum LinkFrameState {
     #[default]
     Default,
     Saved,
     Inverted,
 }
+impl From<u8> for LinkFrameState {
+    fn from(value: u8) -> Self {
+        match value {
+            1 => LinkFrameState::Saved,
+            2 => LinkFrameState::Inverted,
+            _ => LinkFrameState::Default,
+        }
+    }
+}
 impl Display for LinkFrameState {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let display = match self {
@@ -310,18 +372,51 @@ impl Display for LinkFrameState {
     }
 }
 
-#[derive(Debug, Default)]
+/// Cloning shares the image and the texture, only the data around them is copied.
+#[derive(Clone, Derivative)]
+#[derivative(Debug, Default, Hash)]
 pub struct HatBase {
     pub hat_type: HatType,
     pub frame_size: IVec2,
     pub hat_area_size: IVec2,
-    pub bitmap: Option<Bitmap>,
+    #[derivative(Debug = "ignore", Hash = "ignore")]
+    pub bitmap: Option<Rc<Bitmap>>,
+    #[derivative(Debug = "ignore", Hash = "ignore")]
     pub texture: Option<Texture>,
     pub name: Option<String>,
+    #[derivative(Hash = "ignore")]
+    pub path: Option<PathBuf>,
+    #[derivative(Hash = "ignore")]
+    pub metapixel_errors: Vec<MetapixelParseError>,
+    /// Metapixels the editor does not understand, and raw ones added by hand.
+    /// They are written after the generated ones on save.
+    pub unknown_metapixels: Vec<Metapixel>,
     pub id: HatElementId,
 }
 
-#[derive(Debug, Derivative)]
+impl HatBase {
+    /// Size of the element image. The texture wins over the bitmap,
+    /// since it is the one that gets hot-reloaded.
+    pub fn image_size(&self) -> IVec2 {
+        if let Some(texture) = &self.texture {
+            return IVec2::new(texture.width(), texture.height());
+        }
+        self.bitmap
+            .as_ref()
+            .map(|b| IVec2::new(b.width as i32, b.height as i32))
+            .unwrap_or(IVec2::ZERO)
+    }
+
+    pub fn load_texture(&mut self, gl: &Context) -> Result<()> {
+        let Some(bitmap) = &self.bitmap else {
+            bail!("unable to create texture: no bitmap found");
+        };
+        self.texture = Some(Texture::from_bitmap(gl, bitmap, self.path.clone())?);
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, Hash, Derivative)]
 #[derivative(Default)]
 pub struct FlyingPet {
     pub pet_base: PetBase,
@@ -331,67 +426,45 @@ pub struct FlyingPet {
     pub speed: i32,
 }
 impl LoadHat for FlyingPet {
-    fn load_from_name_and_size(
-        path: impl AsRef<Path>,
-        name_and_size: HatNameAndSize,
-        gl: &Context,
-    ) -> Result<Self> {
-        let (metapixels, size) = get_metapixels_and_size(path.as_ref(), &name_and_size)?;
-        let texture = Texture::from_path(gl, &path)?;
+    fn from_bitmap(bitmap: Bitmap, name_and_size: HatNameAndSize) -> Result<Self> {
+        let (decoded, size) = get_metapixels_and_size(&bitmap, &name_and_size);
+        let metapixels = decoded.metapixels;
         let mut hat = FlyingPet {
             hat_base: HatBase {
                 id: hat_id(),
-                name: if name_and_size.is_name_valid() {
+                name: if name_and_size.is_name_valid(HatType::FlyingPet) {
                     Some(name_and_size.name)
                 } else {
                     None
                 },
                 hat_area_size: size,
-                bitmap: Bitmap::from_path(path.as_ref()).ok(),
+                bitmap: Some(Rc::new(bitmap)),
                 hat_type: HatType::FlyingPet,
                 frame_size: (MIN_FRAME_SIZE, MIN_FRAME_SIZE).into(),
-                texture: Some(texture),
+                texture: None,
+                path: None,
+                metapixel_errors: decoded.errors,
+                unknown_metapixels: decoded.unknown,
             },
             ..Default::default()
         };
-        for (i, pixel) in metapixels.iter().enumerate() {
-            match pixel.get_type() {
-                MetapixelType::PetDistance => hat.pet_base.distance = pixel.g as i32,
-                MetapixelType::PetNoFlip => hat.pet_base.flipped = false,
-                MetapixelType::FrameSize => {
-                    hat.hat_base.frame_size = IVec2::new(pixel.g as i32, pixel.b as i32)
-                }
-                MetapixelType::AnimationType => {
-                    if let Some(anim) = get_animation(&metapixels, i) {
-                        hat.pet_base.animations.push(RefCell::new(anim).into())
-                    }
-                }
-                MetapixelType::IsBigHat => hat.pet_base.is_big = true,
-                MetapixelType::PetChangesAngle => hat.changes_angle = true,
-                MetapixelType::PetSpeed => hat.speed = pixel.g as i32,
-                _ => (),
-            };
-        }
+        read_metapixels(&mut hat, &metapixels);
         Ok(hat)
     }
 }
 
-#[derive(Debug, Default)]
+#[derive(Clone, Debug, Default, Hash)]
 pub struct WalkingPet {
     pub pet_base: PetBase,
     pub hat_base: HatBase,
 }
 impl LoadHat for WalkingPet {
-    fn load_from_name_and_size(
-        path: impl AsRef<Path>,
-        name_and_size: HatNameAndSize,
-        gl: &Context,
-    ) -> Result<Self> {
-        let (metapixels, size) = get_metapixels_and_size(path.as_ref(), &name_and_size)?;
-        let texture = Texture::from_path(gl, &path)?;
+    fn from_bitmap(bitmap: Bitmap, name_and_size: HatNameAndSize) -> Result<Self> {
+        let (decoded, size) = get_metapixels_and_size(&bitmap, &name_and_size);
+        let metapixels = decoded.metapixels;
         let mut hat = WalkingPet {
             hat_base: HatBase {
-                name: if name_and_size.is_name_valid() {
+                name: if name_and_size.is_name_valid(HatType::WalkingPet) {
                     Some(name_and_size.name)
                 } else {
                     None
@@ -399,34 +472,22 @@ impl LoadHat for WalkingPet {
                 id: hat_id(),
                 frame_size: (MIN_FRAME_SIZE, MIN_FRAME_SIZE).into(),
                 hat_area_size: size,
-                bitmap: Bitmap::from_path(path.as_ref()).ok(),
+                bitmap: Some(Rc::new(bitmap)),
                 hat_type: HatType::WalkingPet,
-                texture: Some(texture),
+                texture: None,
+                path: None,
+                metapixel_errors: decoded.errors,
+                unknown_metapixels: decoded.unknown,
             },
             ..Default::default()
         };
-        for (i, pixel) in metapixels.iter().enumerate() {
-            match pixel.get_type() {
-                MetapixelType::PetDistance => hat.pet_base.distance = pixel.g as i32,
-                MetapixelType::PetNoFlip => hat.pet_base.flipped = false,
-                MetapixelType::FrameSize => {
-                    hat.hat_base.frame_size = IVec2::new(pixel.g as i32, pixel.b as i32)
-                }
-                MetapixelType::AnimationType => {
-                    if let Some(anim) = get_animation(&metapixels, i) {
-                        hat.pet_base.animations.push(RefCell::new(anim).into())
-                    }
-                }
-                MetapixelType::IsBigHat => hat.pet_base.is_big = true,
-                _ => (),
-            };
-        }
+        read_metapixels(&mut hat, &metapixels);
         Ok(hat)
     }
 }
 
-#[derive(Debug, Derivative)]
-#[derivative(Default)]
+#[derive(Clone, Debug, Derivative)]
+#[derivative(Default, Hash)]
 pub struct PetBase {
     #[derivative(Default(value = "DEFAULT_PET_DISTANCE"))]
     pub distance: i32,
@@ -434,103 +495,22 @@ pub struct PetBase {
     pub flipped: bool,
     pub is_big: bool,
     pub link_frame_state: LinkFrameState,
+    #[derivative(Hash(hash_with = "hash_animations"))]
     pub animations: Vec<AnimationCell>,
 }
 
-impl GenMetapixels for FlyingPet {
-    fn gen_metapixels(&self) -> Vec<Metapixel> {
-        let mut metapixels = Metapixels::new();
-        if self.pet_base.distance != DEFAULT_PET_DISTANCE {
-            metapixels.push(MetapixelType::PetDistance, self.pet_base.distance as u8, 0);
-        }
-        if self.pet_base.flipped {
-            metapixels.push(MetapixelType::PetNoFlip, 0, 0);
-        }
-        if self.base().frame_size.x > 32 || self.base().frame_size.y > 32 {
-            metapixels.push(MetapixelType::IsBigHat, 0, 0);
-        }
-        metapixels.push(
-            MetapixelType::FrameSize,
-            self.base().frame_size.x as u8,
-            self.base().frame_size.y as u8,
-        );
-        if !matches!(self.pet_base.link_frame_state, LinkFrameState::Default) {
-            metapixels.push(
-                MetapixelType::LinkFrameState,
-                self.pet_base.link_frame_state as u8,
-                0,
-            );
-        }
-        if self.changes_angle {
-            metapixels.push(MetapixelType::PetChangesAngle, 0, 0);
-        }
-        if self.speed != DEFAULT_PET_SPEED {
-            metapixels.push(MetapixelType::PetSpeed, self.speed as u8, 0);
-        }
-
-        for anim in &self.pet_base.animations {
-            for pixel in anim.borrow().gen_metapixels() {
-                metapixels.push_raw(pixel);
-            }
-        }
-
-        metapixels.pixels
-    }
-}
-impl GenMetapixels for WalkingPet {
-    fn gen_metapixels(&self) -> Vec<Metapixel> {
-        let mut metapixels = Metapixels::new();
-        if self.pet_base.distance != DEFAULT_PET_DISTANCE {
-            metapixels.push(MetapixelType::PetDistance, self.pet_base.distance as u8, 0);
-        }
-        if self.pet_base.flipped {
-            metapixels.push(MetapixelType::PetNoFlip, 0, 0);
-        }
-        if self.base().frame_size.x > 32 || self.base().frame_size.y > 32 {
-            metapixels.push(MetapixelType::IsBigHat, 0, 0);
-        }
-        metapixels.push(
-            MetapixelType::FrameSize,
-            self.base().frame_size.x as u8,
-            self.base().frame_size.y as u8,
-        );
-        if !matches!(self.pet_base.link_frame_state, LinkFrameState::Default) {
-            metapixels.push(
-                MetapixelType::LinkFrameState,
-                self.pet_base.link_frame_state as u8,
-                0,
-            );
-        }
-
-        for anim in &self.pet_base.animations {
-            for pixel in anim.borrow().gen_metapixels() {
-                metapixels.push_raw(pixel);
-            }
-        }
-
-        metapixels.pixels
-    }
-}
-
-#[derive(Debug, Default)]
+#[derive(Clone, Debug, Default, Hash)]
 pub struct Preview {
     pub base: HatBase,
 }
 
-impl GenMetapixels for Preview {
-    fn gen_metapixels(&self) -> Vec<Metapixel> {
-        vec![]
-    }
-}
-
 impl LoadHat for Preview {
-    fn load_from_path(path: impl AsRef<Path>, gl: &Context) -> Result<Self> {
-        let bitmap = Bitmap::from_path(path.as_ref())?;
-        let texture = Texture::from_path(gl, &path)?;
-        let name_and_size = get_name_and_size(&path.file_stem_string().unwrap_or_default());
-        Ok(Preview {
+    /// Previews have no metapixels of their own, any found are kept as unknown.
+    fn from_bitmap(bitmap: Bitmap, name_and_size: HatNameAndSize) -> Result<Self> {
+        let (decoded, size) = get_metapixels_and_size(&bitmap, &name_and_size);
+        let mut hat = Preview {
             base: HatBase {
-                name: if name_and_size.is_name_valid() {
+                name: if name_and_size.is_name_valid(HatType::Preview) {
                     Some(name_and_size.name)
                 } else {
                     None
@@ -538,24 +518,21 @@ impl LoadHat for Preview {
                 id: hat_id(),
                 hat_type: HatType::Preview,
                 frame_size: (MIN_FRAME_SIZE, MIN_FRAME_SIZE).into(),
-                hat_area_size: (bitmap.width as i32, bitmap.height as i32).into(),
-                bitmap: Some(bitmap),
-                texture: Some(texture),
+                hat_area_size: size,
+                bitmap: Some(Rc::new(bitmap)),
+                texture: None,
+                path: None,
+                metapixel_errors: decoded.errors,
+                unknown_metapixels: decoded.unknown,
             },
-        })
-    }
-
-    fn load_from_name_and_size(
-        path: impl AsRef<Path>,
-        _: HatNameAndSize,
-        gl: &Context,
-    ) -> Result<Self> {
-        LoadHat::load_from_path(path.as_ref(), gl)
+        };
+        read_metapixels(&mut hat, &decoded.metapixels);
+        Ok(hat)
     }
 }
 
-#[derive(Debug, Derivative)]
-#[derivative(Default)]
+#[derive(Clone, Debug, Derivative)]
+#[derivative(Default, Hash)]
 pub struct Wings {
     #[derivative(Default(value = "IVec2::new(128,128)"))]
     pub general_offset: IVec2,
@@ -574,172 +551,57 @@ pub struct Wings {
     pub changes_animations: bool,
     pub size_state: bool,
     pub base: HatBase,
+    #[derivative(Hash(hash_with = "hash_animations"))]
     pub animations: Vec<AnimationCell>,
 }
 
 impl LoadHat for Wings {
-    fn load_from_name_and_size(
-        path: impl AsRef<Path>,
-        name_and_size: HatNameAndSize,
-        gl: &Context,
-    ) -> Result<Self> {
-        let (metapixels, size) = get_metapixels_and_size(path.as_ref(), &name_and_size)?;
-        let texture = Texture::from_path(gl, &path)?;
+    fn from_bitmap(bitmap: Bitmap, name_and_size: HatNameAndSize) -> Result<Self> {
+        let (decoded, size) = get_metapixels_and_size(&bitmap, &name_and_size);
+        let metapixels = decoded.metapixels;
         let mut hat = Wings {
             base: HatBase {
-                name: if name_and_size.is_name_valid() {
+                name: if name_and_size.is_name_valid(HatType::Wings) {
                     Some(name_and_size.name)
                 } else {
                     None
                 },
                 id: hat_id(),
                 hat_area_size: size,
-                bitmap: Bitmap::from_path(path.as_ref()).ok(),
+                bitmap: Some(Rc::new(bitmap)),
                 hat_type: HatType::Wings,
                 frame_size: (MIN_FRAME_SIZE, MIN_FRAME_SIZE).into(),
-                texture: Some(texture),
+                texture: None,
+                path: None,
+                metapixel_errors: decoded.errors,
+                unknown_metapixels: decoded.unknown,
             },
             ..Default::default()
         };
-        let mut has_auto_speed = false;
-
-        hat.auto_glide_frame = hat.frames_amount() as i32;
-        hat.auto_idle_frame = DEFAULT_WINGS_IDLE_FRAME;
-        for pixel in metapixels {
-            match pixel.get_type() {
-                MetapixelType::WingsNetOffset => {
-                    hat.net_offset = IVec2::new(pixel.g as i32 - 128, pixel.b as i32 - 128)
-                }
-                MetapixelType::WingsGeneralOffset => {
-                    hat.general_offset = IVec2::new(pixel.g as i32 - 128, pixel.b as i32 - 128)
-                }
-                MetapixelType::WingsSlideOffset => {
-                    hat.slide_offset = IVec2::new(pixel.g as i32 - 128, pixel.b as i32 - 128)
-                }
-                MetapixelType::WingsRagdollOffset => {
-                    hat.ragdoll_offset = IVec2::new(pixel.g as i32 - 128, pixel.b as i32 - 128)
-                }
-                MetapixelType::WingsCrouchOffset => {
-                    hat.crouch_offset = IVec2::new(pixel.g as i32 - 128, pixel.b as i32 - 128)
-                }
-                MetapixelType::GenerateWingsAnimations => hat.gen_animations = true,
-                MetapixelType::WingsAutoGlideFrame => {
-                    hat.auto_glide_frame = pixel.g.saturating_add(1) as i32
-                }
-                MetapixelType::WingsAutoIdleFrame => {
-                    hat.auto_idle_frame = pixel.g.saturating_add(1) as i32
-                }
-                MetapixelType::WingsAutoAnimationsSpeed => {
-                    hat.auto_anim_speed = pixel.g as i32;
-                    has_auto_speed = true;
-                }
-                MetapixelType::FrameSize => {
-                    hat.base.frame_size = IVec2::new(pixel.g as i32, pixel.b as i32)
-                }
-                MetapixelType::ChangeAnimationsEveryLevel => hat.changes_animations = true,
-                MetapixelType::IsBigHat => hat.size_state = true,
-                _ => (),
-            }
-        }
-        hat.auto_anim_speed = if has_auto_speed {
-            hat.auto_anim_speed
-        } else {
-            DEFAULT_AUTO_SPEED
-        };
-        hat.animations.push(RefCell::new(Animation::new(
-            AnimType::OnDefault,
-            hat.auto_anim_speed,
-            false,
-            frames_from_range(0, hat.frames_amount() as i32 - 1),
-        )).into());
-
+        read_metapixels(&mut hat, &metapixels);
         Ok(hat)
     }
 }
 
-//TODO: add animations for wings
-impl GenMetapixels for Wings {
-    fn gen_metapixels(&self) -> Vec<Metapixel> {
-        let mut metapixels = Metapixels::new();
-        if self.general_offset.x != 128 || self.general_offset.y != 128 {
-            metapixels.push(
-                MetapixelType::WingsGeneralOffset,
-                self.general_offset.x as u8,
-                self.general_offset.y as u8,
-            );
-        }
-        if self.slide_offset.x != 128 || self.slide_offset.y != 128 {
-            metapixels.push(
-                MetapixelType::WingsSlideOffset,
-                self.slide_offset.x as u8,
-                self.slide_offset.y as u8,
-            );
-        }
-        if self.ragdoll_offset.x != 128 || self.ragdoll_offset.y != 128 {
-            metapixels.push(
-                MetapixelType::WingsRagdollOffset,
-                self.ragdoll_offset.x as u8,
-                self.ragdoll_offset.y as u8,
-            );
-        }
-        if self.crouch_offset.x != 128 || self.net_offset.y != 128 {
-            metapixels.push(
-                MetapixelType::WingsCrouchOffset,
-                self.crouch_offset.x as u8,
-                self.crouch_offset.y as u8,
-            );
-        }
-        if self.net_offset.x != 128 || self.net_offset.y != 128 {
-            metapixels.push(
-                MetapixelType::WingsNetOffset,
-                self.net_offset.x as u8,
-                self.net_offset.y as u8,
-            );
-        }
-        if self.base.frame_size.x > 32 || self.base.frame_size.y > 32 {
-            metapixels.push(MetapixelType::IsBigHat, 0, 0);
-        }
-        if self.gen_animations {
-            metapixels.push(MetapixelType::GenerateWingsAnimations, 0, 0);
-        }
-        if self.changes_animations {
-            metapixels.push(MetapixelType::ChangeAnimationsEveryLevel, 0, 0);
-        }
-        if self.auto_anim_speed != DEFAULT_AUTO_SPEED {
-            metapixels.push(
-                MetapixelType::WingsAutoAnimationsSpeed,
-                self.auto_anim_speed as u8,
-                0,
-            );
-        }
-        //don't substract one since auto glide frame starts from one
-        if self.auto_glide_frame != self.frames_amount() as i32 {
-            metapixels.push(
-                MetapixelType::WingsAutoGlideFrame,
-                (self.auto_glide_frame as u8).saturating_sub(1),
-                0,
-            );
-        }
-        if self.auto_idle_frame != DEFAULT_WINGS_IDLE_FRAME {
-            metapixels.push(
-                MetapixelType::WingsAutoIdleFrame,
-                (self.auto_idle_frame as u8).saturating_sub(1),
-                0,
-            );
-        }
-        metapixels.push(
-            MetapixelType::FrameSize,
-            self.base.frame_size.x as u8,
-            self.base.frame_size.y as u8,
-        );
-        metapixels.pixels
+impl Wings {
+    /// What the game plays when it generates the animations itself,
+    /// used to preview wings that have none of their own.
+    pub fn auto_animation(&self) -> Animation {
+        Animation::new(
+            AnimType::OnDefault,
+            self.auto_anim_speed,
+            false,
+            frames_from_range(0, self.frames_amount() as i32 - 1),
+        )
     }
 }
 
-#[derive(Debug, Default)]
+#[derive(Clone, Debug, Derivative)]
+#[derivative(Default, Hash)]
 pub struct Wereable {
     pub strapped_on: bool,
     pub is_big: bool,
+    #[derivative(Hash(hash_with = "hash_animations"))]
     pub animations: Vec<AnimationCell>,
     pub link_frame_state: LinkFrameState,
     pub on_spawn_animation: Option<AnimType>,
@@ -747,189 +609,152 @@ pub struct Wereable {
 }
 
 impl LoadHat for Wereable {
-    fn load_from_name_and_size(
-        path: impl AsRef<Path>,
-        name_and_size: HatNameAndSize,
-        gl: &Context,
-    ) -> Result<Self> {
-        let texture = Texture::from_path(gl, &path)?;
-        let (metapixels, size) = get_metapixels_and_size(path.as_ref(), &name_and_size)?;
+    fn from_bitmap(bitmap: Bitmap, name_and_size: HatNameAndSize) -> Result<Self> {
+        let (decoded, size) = get_metapixels_and_size(&bitmap, &name_and_size);
+        let metapixels = decoded.metapixels;
         let mut hat: Wereable = Wereable {
             base: HatBase {
                 id: hat_id(),
                 hat_area_size: size,
-                bitmap: Bitmap::from_path(path.as_ref()).ok(),
+                bitmap: Some(Rc::new(bitmap)),
                 hat_type: HatType::Wereable,
-                name: if name_and_size.is_name_valid() {
+                name: if name_and_size.is_name_valid(HatType::Wereable) {
                     Some(name_and_size.name)
                 } else {
                     None
                 },
                 frame_size: (MIN_FRAME_SIZE, MIN_FRAME_SIZE).into(),
-                texture: Some(texture),
+                texture: None,
+                path: None,
+                metapixel_errors: decoded.errors,
+                unknown_metapixels: decoded.unknown,
             },
             ..Default::default()
         };
-        for (i, pixel) in metapixels.iter().enumerate() {
-            match pixel.get_type() {
-                MetapixelType::OnSpawnAnimation => {
-                    hat.on_spawn_animation = AnimType::from_u8(pixel.g);
-                }
-                MetapixelType::StrappedOn => hat.strapped_on = true,
-                MetapixelType::IsBigHat => hat.is_big = true,
-                MetapixelType::FrameSize => {
-                    hat.base.frame_size = IVec2::new(pixel.g as i32, pixel.b as i32)
-                }
-                MetapixelType::LinkFrameState => {
-                    hat.link_frame_state = match pixel.g {
-                        1 => LinkFrameState::Saved,
-                        2 => LinkFrameState::Inverted,
-                        _ => LinkFrameState::Default,
-                    }
-                }
-                MetapixelType::AnimationType => {
-                    if let Some(anim) = get_animation(&metapixels, i) {
-                        hat.animations.push(RefCell::new(anim).into())
-                    }
-                }
-                _ => {}
-            }
-        }
+        read_metapixels(&mut hat, &metapixels);
         Ok(hat)
     }
 }
 
-impl GenMetapixels for Wereable {
-    fn gen_metapixels(&self) -> Vec<Metapixel> {
-        let mut metapixels = Metapixels::new();
-        if self.strapped_on {
-            metapixels.push(MetapixelType::StrappedOn, 0, 0);
-        }
-        if self.base.frame_size.x > 32 || self.base.frame_size.y > 32 {
-            metapixels.push(MetapixelType::IsBigHat, 0, 0);
-        }
-        metapixels.push(
-            MetapixelType::FrameSize,
-            self.base.frame_size.x as u8,
-            self.base.frame_size.y as u8,
-        );
-        if let Some(spawn_anim) = self.on_spawn_animation {
-            metapixels.push(MetapixelType::OnSpawnAnimation, spawn_anim as u8, 0);
-        }
-        if !matches!(self.link_frame_state, LinkFrameState::Default) {
-            metapixels.push(
-                MetapixelType::LinkFrameState,
-                self.link_frame_state as u8,
-                0,
-            );
-        }
-        for anim in &self.animations {
-            for pixel in anim.borrow().gen_metapixels() {
-                metapixels.push_raw(pixel);
-            }
-        }
-        metapixels.pixels
-    }
-}
-
-#[derive(Debug, Default)]
+#[derive(Clone, Debug, Default, Hash)]
 pub struct RoomHat {
     pub base: HatBase,
 }
 impl LoadHat for RoomHat {
-    fn load_from_name_and_size(
-        path: impl AsRef<Path>,
-        name_and_size: HatNameAndSize,
-        gl: &Context,
-    ) -> Result<Self> {
-        let (_, size) = get_metapixels_and_size(path.as_ref(), &name_and_size)?;
-        let texture = Texture::from_path(gl, &path)?;
-        let hat = RoomHat {
+    /// Rooms have no metapixels of their own, any found are kept as unknown.
+    fn from_bitmap(bitmap: Bitmap, name_and_size: HatNameAndSize) -> Result<Self> {
+        let (decoded, size) = get_metapixels_and_size(&bitmap, &name_and_size);
+        let mut hat = RoomHat {
             base: HatBase {
                 id: hat_id(),
                 hat_area_size: size,
-                bitmap: Bitmap::from_path(path.as_ref()).ok(),
+                bitmap: Some(Rc::new(bitmap)),
                 hat_type: HatType::Room,
                 frame_size: size,
-                name: if name_and_size.is_name_valid() {
+                name: if name_and_size.is_name_valid(HatType::Room) {
                     Some(name_and_size.name)
                 } else {
                     None
                 },
-                texture: Some(texture),
+                texture: None,
+                path: None,
+                metapixel_errors: decoded.errors,
+                unknown_metapixels: decoded.unknown,
             },
         };
+        read_metapixels(&mut hat, &decoded.metapixels);
         Ok(hat)
     }
 }
 
-impl GenMetapixels for RoomHat {
-    fn gen_metapixels(&self) -> Vec<Metapixel> {
-        vec![]
-    }
-}
-
-#[derive(Debug, Default)]
+#[derive(Clone, Debug, Derivative)]
+#[derivative(Default, Hash)]
 pub struct Extra {
     pub base: HatBase,
+    #[derivative(Hash(hash_with = "hash_animations"))]
     pub animations: Vec<AnimationCell>,
 }
 
 impl LoadHat for Extra {
-    fn load_from_name_and_size(
-        path: impl AsRef<Path>,
-        name_and_size: HatNameAndSize,
-        gl: &Context,
-    ) -> Result<Self> {
-        let texture = Texture::from_path(gl, &path)?;
-        let (metapixels, size) = get_metapixels_and_size(path.as_ref(), &name_and_size)?;
+    fn from_bitmap(bitmap: Bitmap, name_and_size: HatNameAndSize) -> Result<Self> {
+        let (decoded, size) = get_metapixels_and_size(&bitmap, &name_and_size);
+        let metapixels = decoded.metapixels;
         let mut hat = Extra {
             base: HatBase {
-                name: if name_and_size.is_name_valid() {
+                name: if name_and_size.is_name_valid(HatType::Extra) {
                     Some(name_and_size.name)
                 } else {
                     None
                 },
                 id: hat_id(),
                 hat_area_size: size,
-                bitmap: Bitmap::from_path(path.as_ref()).ok(),
                 hat_type: HatType::Extra,
                 frame_size: (
-                    i32::min(texture.width(), MAX_EXTRA_HAT_SIZE.x),
-                    i32::min(texture.height(), MAX_EXTRA_HAT_SIZE.y),
+                    i32::min(bitmap.width as i32, MAX_EXTRA_HAT_SIZE.x),
+                    i32::min(bitmap.height as i32, MAX_EXTRA_HAT_SIZE.y),
                 )
                     .into(),
-                texture: Some(texture),
+                bitmap: Some(Rc::new(bitmap)),
+                texture: None,
+                path: None,
+                metapixel_errors: decoded.errors,
+                unknown_metapixels: decoded.unknown,
             },
             ..Default::default()
         };
 
-        hat.animations.push(RefCell::new(Animation::new(
+        read_metapixels(&mut hat, &metapixels);
+        Ok(hat)
+    }
+}
+
+impl Extra {
+    /// Plays every frame in order, used to preview extra hats without animations.
+    pub fn default_animation(&self) -> Animation {
+        Animation::new(
             AnimType::OnDefault,
             4,
             false,
-            frames_from_range(0, hat.frames_amount() as i32 - 1),
-        )).into());
+            frames_from_range(0, self.frames_amount() as i32 - 1),
+        )
+    }
+}
 
-        for pixel in metapixels {
-            if let MetapixelType::FrameSize = pixel.get_type() {
-                hat.base.frame_size = IVec2::new(pixel.g as i32, pixel.b as i32)
+/// Problems found while loading a hat, see [`Hat::load_with_report`].
+#[derive(Debug, Default)]
+pub struct HatLoadReport {
+    pub failed_files: Vec<(PathBuf, String)>,
+    pub metapixel_errors: Vec<(PathBuf, MetapixelParseError)>,
+}
+
+impl HatLoadReport {
+    pub fn is_empty(&self) -> bool {
+        self.failed_files.is_empty() && self.metapixel_errors.is_empty()
+    }
+    /// Adds the metapixel errors of every element, then sorts everything for stable output.
+    pub fn collect_metapixel_errors(&mut self, hat: &Hat) {
+        for element in hat.iter_all_elements() {
+            let base = element.base();
+            let path = base.path.clone().unwrap_or_default();
+            for error in &base.metapixel_errors {
+                self.metapixel_errors.push((path.clone(), *error));
             }
         }
-
-        Ok(hat)
+        self.failed_files.sort();
+        self.metapixel_errors
+            .sort_by_key(|(path, e)| (path.clone(), e.x, e.y));
     }
 }
 
-impl GenMetapixels for Extra {
-    fn gen_metapixels(&self) -> Vec<Metapixel> {
-        let mut metapixels = Metapixels::new();
-        metapixels.push(
-            MetapixelType::FrameSize,
-            self.base.frame_size.x as u8,
-            self.base.frame_size.y as u8,
-        );
-        metapixels.pixels
+impl Display for HatLoadReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (path, error) in &self.failed_files {
+            writeln!(f, "{}: {error}", path.display())?;
+        }
+        for (path, error) in &self.metapixel_errors {
+            writeln!(f, "{}: {error}", path.display())?;
+        }
+        Ok(())
     }
 }
 
@@ -938,6 +763,24 @@ pub struct Hat {
     pub unique_elemets: HashMap<HatType, Box<dyn AbstractHat>>,
     pub pets: Vec<Box<dyn AbstractHat>>,
     pub path: Option<PathBuf>,
+    /// Element files the hat was loaded from or last saved to. Saving into their folder
+    /// removes the ones it does not write anymore, every other file is left alone.
+    pub files: Vec<PathBuf>,
+}
+
+impl Clone for Hat {
+    fn clone(&self) -> Self {
+        Self {
+            unique_elemets: self
+                .unique_elemets
+                .iter()
+                .map(|(hat_type, element)| (*hat_type, element.clone_boxed()))
+                .collect(),
+            pets: self.pets.iter().map(|pet| pet.clone_boxed()).collect(),
+            path: self.path.clone(),
+            files: self.files.clone(),
+        }
+    }
 }
 
 impl Hat {
@@ -946,6 +789,7 @@ impl Hat {
             path: Some(path),
             pets: vec![],
             unique_elemets: HashMap::new(),
+            files: vec![],
         }
     }
     pub fn hat_type_by_id(&self, id: HatElementId) -> Option<HatType> {
@@ -1080,14 +924,45 @@ impl Hat {
         self.pets.retain(|e| e.id() != id);
     }
     pub fn add_element(&mut self, element: impl AbstractHat) {
+        self.add_boxed_element(Box::new(element));
+    }
+    pub fn add_boxed_element(&mut self, element: Box<dyn AbstractHat>) {
         match element.base().hat_type {
-            HatType::WalkingPet | HatType::FlyingPet => self.add_pet(Box::new(element)),
-            _ => self.add_unique_hat(element.base().hat_type, Box::new(element)),
+            HatType::WalkingPet | HatType::FlyingPet => self.add_pet(element),
+            hat_type => self.add_unique_hat(hat_type, element),
         };
     }
-    pub fn add_pet(&mut self, hat: Box<dyn AbstractHat>) {
+    /// Pets of one type share a file name, so every pet gets a name of its own,
+    /// like `walkingpet2`, instead of overwriting the others on save.
+    pub fn add_pet(&mut self, mut hat: Box<dyn AbstractHat>) {
+        self.name_new_pet(&mut *hat);
         self.pets.push(hat);
     }
+    /// Gives a pet that is about to be added the name it will be saved under.
+    pub fn name_new_pet(&self, hat: &mut dyn AbstractHat) {
+        let has_free_name = match &hat.base().name {
+            Some(name) => !self.is_pet_name_taken(name),
+            None => false,
+        };
+        if !has_free_name {
+            hat.base_mut().name = Some(self.free_pet_name(hat.base().hat_type));
+        }
+    }
+    fn is_pet_name_taken(&self, name: &str) -> bool {
+        self.pets.iter().any(|pet| {
+            pet.base()
+                .name
+                .as_ref()
+                .is_some_and(|n| n.eq_ignore_ascii_case(name))
+        })
+    }
+    fn free_pet_name(&self, hat_type: HatType) -> String {
+        let save_name = hat_type.save_name();
+        std::iter::once(save_name.to_string())
+            .chain((2..).map(|i| format!("{save_name}{i}")))
+            .find(|name| !self.is_pet_name_taken(name))
+            .unwrap()
+    }
     pub fn add_unique_hat(&mut self, hat_type: HatType, hat: Box<dyn AbstractHat>) {
         let is_specified = !matches!(hat_type, HatType::Unspecified);
         assert!(is_specified);
@@ -1105,118 +980,457 @@ impl Hat {
         }
         self.pets.first().map(|e| (&**e, e.base().hat_type))
     }
-    pub fn save(&self, dir_path: impl AsRef<Path>) -> Result<()> {
-        let path = dir_path.as_ref();
-        std::fs::remove_dir_all(path)?;
-        std::fs::create_dir(path)?;
-        for element in self.unique_elemets.values() {
-            element.save(path)?;
+    /// File name and image of every element, the way they are saved.
+    pub fn encode(&self) -> Result<Vec<(String, Bitmap)>> {
+        self.unique_elemets
+            .values()
+            .chain(self.pets.iter())
+            .map(|element| element.encode())
+            .collect()
+    }
+    /// Saves the hat without touching files the editor does not own. Every element is
+    /// encoded and written to a temporary folder first, the files it replaces are copied
+    /// to a backup and element files of the hat that are no longer used get removed.
+    pub fn save(&mut self, dir_path: impl AsRef<Path>) -> Result<()> {
+        let dir = dir_path.as_ref();
+        let files = self.encode()?;
+        std::fs::create_dir_all(dir)?;
+
+        let temp_dir = dir.join(SAVE_TEMP_DIR);
+        if temp_dir.exists() {
+            std::fs::remove_dir_all(&temp_dir)?;
         }
-        for pet in &self.pets {
-            pet.save(path)?;
+        std::fs::create_dir(&temp_dir)?;
+        // the hat may have been loaded through another path to the same folder
+        let canonical_dir = std::fs::canonicalize(dir)?;
+        let stale = self
+            .files
+            .iter()
+            .filter(|path| {
+                let parent = path.parent().and_then(|p| std::fs::canonicalize(p).ok());
+                parent.is_some_and(|p| p == canonical_dir)
+            })
+            .filter_map(|path| Some(dir.join(path.file_name()?)))
+            .collect::<Vec<_>>();
+        let result = swap_in_files(dir, &temp_dir, &files, &stale);
+        std::fs::remove_dir_all(&temp_dir).ok();
+        result?;
+        self.files = files.iter().map(|(name, _)| dir.join(name)).collect();
+        Ok(())
+    }
+
+    pub fn load_textures(&mut self, gl: &Context) -> Result<()> {
+        for element in self.iter_all_elements_mut() {
+            element.base_mut().load_texture(gl)?;
         }
         Ok(())
     }
 
-    pub fn load(dir_path: impl AsRef<Path>, gl: &eframe::glow::Context) -> Result<Hat> {
+    pub fn load(dir_path: impl AsRef<Path>) -> Result<Hat> {
+        Self::load_with_report(dir_path).map(|(hat, _)| hat)
+    }
+
+    /// Loads every element it can and reports the rest, instead of
+    /// stopping at the first broken file.
+    pub fn load_with_report(dir_path: impl AsRef<Path>) -> Result<(Hat, HatLoadReport)> {
         let path = dir_path.as_ref();
         if !path.exists() {
             bail!("path to hat was not found: {:?}", path);
         }
 
         let mut hat = Hat::new(path.to_path_buf());
+        let mut report = HatLoadReport::default();
 
-        for entry in std::fs::read_dir(path)?.flatten() {
-            let Some(file_name) = entry
-                .path()
-                .file_stem()
-                .and_then(|p| p.to_str())
-                .map(|s| s.to_owned())
-            else {
+        // Sorted, so that when two files describe the same element the same one always wins.
+        let mut paths = std::fs::read_dir(path)?
+            .flatten()
+            .map(|entry| entry.path())
+            .collect::<Vec<_>>();
+        paths.sort();
+        for path in paths {
+            let Some(file_name) = path.file_stem_string() else {
                 continue;
             };
 
             let name_and_size = get_name_and_size(&file_name);
-            let name_lowercase = name_and_size.name.to_lowercase();
-            match name_lowercase.as_str() {
-                ROOM_NAME => {
-                    if let Ok(room) =
-                        RoomHat::load_from_name_and_size(entry.path(), name_and_size, gl)
-                    {
-                        hat.add_unique_hat(HatType::Room, Box::new(room));
-                    }
-                }
-                EXTRA_NAME => {
-                    if let Ok(extra) =
-                        Extra::load_from_name_and_size(entry.path(), name_and_size, gl)
-                    {
-                        hat.add_unique_hat(HatType::Extra, Box::new(extra));
-                    }
-                }
-                WINGS_NAME => {
-                    if let Ok(wings) =
-                        Wings::load_from_name_and_size(entry.path(), name_and_size, gl)
-                    {
-                        hat.add_unique_hat(HatType::Wings, Box::new(wings));
-                    }
-                }
-                _ if name_lowercase.contains(FLYING_PET_NAME) => {
-                    if let Ok(pet) =
-                        FlyingPet::load_from_name_and_size(entry.path(), name_and_size, gl)
-                    {
-                        hat.add_pet(Box::new(pet));
-                    }
-                }
-                _ if name_lowercase.contains(WALKING_PET_NAME) => {
-                    if let Ok(pet) =
-                        WalkingPet::load_from_name_and_size(entry.path(), name_and_size, gl)
-                    {
-                        hat.add_pet(Box::new(pet));
-                    }
-                }
-                PREVIEW_NAME => {
-                    if let Ok(preview) =
-                        Preview::load_from_name_and_size(entry.path(), name_and_size, gl)
-                    {
-                        hat.add_unique_hat(HatType::Preview, Box::new(preview));
-                    }
-                }
-                WEREABLE_NAME => {
-                    if let Ok(wereable) =
-                        Wereable::load_from_name_and_size(entry.path(), name_and_size, gl)
-                    {
-                        hat.add_unique_hat(HatType::Wereable, Box::new(wereable));
-                    }
-                }
-                _ => {}
+            let Some(hat_type) = HatType::from_file_name(&name_and_size.name) else {
+                continue;
             };
+            if let Err(err) = hat.load_element(hat_type, &path, name_and_size) {
+                report.failed_files.push((path, format!("{err:#}")));
+            }
         }
-        Ok(hat)
+        hat.files = hat
+            .iter_all_elements()
+            .filter_map(|e| e.base().path.clone())
+            .collect();
+        report.collect_metapixel_errors(&hat);
+        Ok((hat, report))
+    }
+
+    pub fn load_element(
+        &mut self,
+        hat_type: HatType,
+        path: impl AsRef<Path>,
+        name_and_size: HatNameAndSize,
+    ) -> Result<()> {
+        let bitmap = Bitmap::from_path(path.as_ref())?;
+        self.add_decoded_element(hat_type, bitmap, name_and_size, path.as_ref().to_owned())
+    }
+    /// Decodes an element from an image that was read from `path` and adds it.
+    pub fn add_decoded_element(
+        &mut self,
+        hat_type: HatType,
+        bitmap: Bitmap,
+        name_and_size: HatNameAndSize,
+        path: PathBuf,
+    ) -> Result<()> {
+        let mut element = decode_element(hat_type, bitmap, name_and_size)?;
+        element.base_mut().path = Some(path);
+        self.add_boxed_element(element);
+        Ok(())
+    }
+
+    /// File name and image an element is saved as.
+    pub fn encode_element(&self, id: HatElementId) -> Result<(String, Bitmap)> {
+        let element = self
+            .unique_elemets
+            .values()
+            .chain(self.pets.iter())
+            .find(|e| e.id() == id);
+        match element {
+            Some(element) => element.encode(),
+            None => bail!("element {id:?} was not found"),
+        }
+    }
+    /// Metapixels the element would be saved with, empty if there is no such element.
+    pub fn element_metapixels(&self, id: HatElementId) -> Vec<Metapixel> {
+        self.unique_elemets
+            .values()
+            .chain(self.pets.iter())
+            .find(|e| e.id() == id)
+            .map(|e| e.gen_metapixels())
+            .unwrap_or_default()
     }
 }
 
-fn insert_metapixels(bitmap: &mut Bitmap, pixels: &[Metapixel], hat_area_size: IVec2) {
-    let mut pixel_iter = pixels.iter().peekable();
-    let (mut x, mut y) = (hat_area_size.x, 0);
-    while x != bitmap.width as i32 {
-        while y != bitmap.height as i32 {
-            let Some(pixel) = pixel_iter.next() else {
-                break;
-            };
-            let pixel = Pixel::from_rgb(pixel.r, pixel.g, pixel.b);
-            bitmap.set_pixel(x, y, pixel);
-            y += 1;
+/// Whether the editor reads and writes the file, a png named after an element type.
+pub fn is_element_file(path: &Path) -> bool {
+    let is_png = path
+        .extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case("png"));
+    let name_and_size = get_name_and_size(&path.file_stem_string().unwrap_or_default());
+    is_png && HatType::from_file_name(&name_and_size.name).is_some()
+}
+
+fn backup_dir(dir: &Path, index: usize) -> PathBuf {
+    match index {
+        0 => dir.join(BACKUP_DIR),
+        _ => dir.join(format!("{BACKUP_DIR}.{index}")),
+    }
+}
+
+/// Shifts `.bak` to `.bak.1` and so on, dropping the oldest one,
+/// and returns the now empty `.bak`.
+fn rotate_backups(dir: &Path) -> Result<PathBuf> {
+    let oldest = backup_dir(dir, BACKUPS_KEPT - 1);
+    if oldest.exists() {
+        std::fs::remove_dir_all(oldest)?;
+    }
+    for index in (0..BACKUPS_KEPT - 1).rev() {
+        let backup = backup_dir(dir, index);
+        if backup.exists() {
+            std::fs::rename(backup, backup_dir(dir, index + 1))?;
+        }
+    }
+    let backup = backup_dir(dir, 0);
+    std::fs::create_dir(&backup)?;
+    Ok(backup)
+}
+
+/// Moves the files from `temp_dir` into `dir` and removes the `owned` files that were
+/// not written again. Files that get replaced or removed are backed up first, and put
+/// back if a file can not be moved in.
+fn swap_in_files(
+    dir: &Path,
+    temp_dir: &Path,
+    files: &[(String, Bitmap)],
+    owned: &[PathBuf],
+) -> Result<()> {
+    for (file_name, bitmap) in files {
+        bitmap.save(temp_dir.join(file_name))?;
+    }
+
+    let mut old_files = files
+        .iter()
+        .map(|(file_name, _)| dir.join(file_name))
+        .chain(owned.iter().cloned())
+        .filter(|path| path.is_file())
+        .collect::<Vec<_>>();
+    old_files.sort();
+    old_files.dedup();
+    let backup = match old_files.is_empty() {
+        true => None,
+        false => Some(rotate_backups(dir)?),
+    };
+    if let Some(backup) = &backup {
+        for path in &old_files {
+            std::fs::copy(path, backup.join(path.file_name().unwrap()))?;
+        }
+    }
 
-            if let Some(next) = pixel_iter.peek() {
-                if matches!(
-                    MetapixelType::from_u8(next.r).unwrap(),
-                    MetapixelType::AnimationType
-                ) {
-                    y += 1;
+    let mut moved_in = vec![];
+    for (file_name, _) in files {
+        let path = dir.join(file_name);
+        if let Err(err) = std::fs::rename(temp_dir.join(file_name), &path) {
+            for path in moved_in {
+                std::fs::remove_file(path).ok();
+            }
+            if let Some(backup) = &backup {
+                for path in &old_files {
+                    std::fs::copy(backup.join(path.file_name().unwrap()), path).ok();
                 }
             }
+            bail!("unable to save {file_name}: {err}");
+        }
+        moved_in.push(path);
+    }
+
+    for path in owned {
+        let file_name = path.file_name().unwrap().to_string_lossy();
+        if files.iter().any(|(name, _)| *name == file_name) {
+            continue;
+        }
+        // on a file system that ignores case, "Hat.png" now is the new "hat.png"
+        let replaced = files
+            .iter()
+            .any(|(name, _)| name.eq_ignore_ascii_case(&file_name) && folds_case(dir, name));
+        if !replaced && path.is_file() {
+            std::fs::remove_file(path)?;
         }
+    }
+    Ok(())
+}
+
+/// Whether `dir` ignores case, checked by looking `file_name` up with its case swapped.
+fn folds_case(dir: &Path, file_name: &str) -> bool {
+    let swapped = file_name
+        .chars()
+        .map(|c| match c.is_ascii_lowercase() {
+            true => c.to_ascii_uppercase(),
+            false => c.to_ascii_lowercase(),
+        })
+        .collect::<String>();
+    swapped != file_name && dir.join(swapped).exists()
+}
+
+pub fn decode_element(
+    hat_type: HatType,
+    bitmap: Bitmap,
+    name_and_size: HatNameAndSize,
+) -> Result<Box<dyn AbstractHat>> {
+    Ok(match hat_type {
+        HatType::Wereable => Box::new(Wereable::from_bitmap(bitmap, name_and_size)?),
+        HatType::Wings => Box::new(Wings::from_bitmap(bitmap, name_and_size)?),
+        HatType::Extra => Box::new(Extra::from_bitmap(bitmap, name_and_size)?),
+        HatType::FlyingPet => Box::new(FlyingPet::from_bitmap(bitmap, name_and_size)?),
+        HatType::WalkingPet => Box::new(WalkingPet::from_bitmap(bitmap, name_and_size)?),
+        HatType::Room => Box::new(RoomHat::from_bitmap(bitmap, name_and_size)?),
+        HatType::Preview => Box::new(Preview::from_bitmap(bitmap, name_and_size)?),
+        HatType::Unspecified => bail!("cannot load an element of unspecified type"),
+    })
+}
+
+/// Frames bigger than 32 pixels need the IsBigHat metapixel.
+pub fn is_big_frame(frame_size: IVec2) -> bool {
+    frame_size.x > 32 || frame_size.y > 32
+}
+
+/// Places metapixels column by column, leaving a blank row before every animation.
+fn layout_metapixels(pixels: &[Metapixel], height: i32) -> Vec<IVec2> {
+    let mut positions = Vec::with_capacity(pixels.len());
+    let (mut x, mut y) = (0, 0);
+    for pixel in pixels {
+        if y != 0 && matches!(pixel.get_type(), Some(MetapixelType::AnimationType)) {
+            y += 1;
+        }
+        if y >= height {
+            x += 1;
+            y = 0;
+        }
+        positions.push(IVec2::new(x, y));
+        y += 1;
+    }
+    positions
+}
+
+fn insert_metapixels(
+    bitmap: &mut Bitmap,
+    pixels: &[Metapixel],
+    positions: &[IVec2],
+    hat_area_size: IVec2,
+) {
+    for (pixel, position) in pixels.iter().zip(positions) {
+        let pixel = Pixel::from_rgb(pixel.r, pixel.g, pixel.b);
+        bitmap.set_pixel(hat_area_size.x + position.x, position.y, pixel);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use bevy_math::IVec2;
+    use pixas::{bitmap::Bitmap, pixel::Pixel};
+
+    use crate::animations::{AnimType, Animation};
+    use crate::hat_utils::{get_name_and_size, HatNameAndSize};
+    use crate::hats::{
+        decode_element, AbstractHat, Hat, HatType, LoadHat, WalkingPet, Wereable, Wings, MAX_PETS,
+    };
+
+    #[test]
+    fn loads_without_gl_context() {
+        let hat = Hat::load("PalasHat").unwrap();
+        assert!(hat.wereable().is_some());
+        assert!(hat.wings().is_some());
+        assert!(hat.extra().is_some());
+        assert!(hat.preview().is_some());
+        assert!(hat.room().is_some());
+        assert_eq!(hat.pets.len(), 1);
+        assert!(hat.iter_all_elements().all(|e| e.texture().is_none()));
+        assert!(hat.iter_all_elements().all(|e| e.base().bitmap.is_some()));
+    }
+
+    #[test]
+    fn keeps_unknown_metapixels_on_save() {
+        let mut bitmap = Bitmap::with_size(33, 32);
+        bitmap.set_pixel(32, 0, Pixel::from_rgb(2, 32, 32));
+        bitmap.set_pixel(32, 1, Pixel::from_rgb(250, 1, 2));
+        bitmap.set_pixel(32, 2, Pixel::from_rgb(22, 3, 4));
+        let name_and_size = HatNameAndSize::new("hat".to_string(), Some(IVec2::new(32, 32)));
+        let wereable = Wereable::from_bitmap(bitmap, name_and_size).unwrap();
+
+        let dir = std::env::temp_dir().join("hpp_keeps_unknown_metapixels");
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut hat = Hat::new(dir.clone());
+        hat.add_element(wereable);
+        hat.save(&dir).unwrap();
+
+        let hat = Hat::load(&dir).unwrap();
+        let unknown = &hat.wereable().unwrap().base.unknown_metapixels;
+        let unknown = unknown.iter().map(|m| (m.r, m.g, m.b)).collect::<Vec<_>>();
+        assert_eq!(unknown, [(250, 1, 2), (22, 3, 4)]);
+        std::fs::remove_dir_all(dir).ok();
+    }
+
+    #[test]
+    fn keeps_wings_animations_on_save() {
+        let name_and_size = HatNameAndSize::new("wings".to_string(), None);
+        let mut wings = Wings::from_bitmap(Bitmap::with_size(96, 32), name_and_size).unwrap();
+        let animation = Animation::new(AnimType::Gliding, 5, true, vec![2.into(), 0.into()]);
+        wings.animations.push(RefCell::new(animation).into());
+        let mut hat = Hat::default();
+        let id = wings.id();
+        hat.add_element(wings);
+
+        let (file_name, bitmap) = hat.encode_element(id).unwrap();
+        let name_and_size = get_name_and_size(file_name.trim_end_matches(".png"));
+        let decoded = decode_element(HatType::Wings, bitmap, name_and_size).unwrap();
+        let animations = decoded.animations().unwrap();
+        assert_eq!(animations.len(), 1);
+        let animation = animations[0].borrow();
+        assert_eq!(animation.anim_type, AnimType::Gliding);
+        assert_eq!((animation.delay, animation.looping), (5, true));
+        let frames = animation.frames.iter().map(|f| f.value).collect::<Vec<_>>();
+        assert_eq!(frames, [2, 0]);
+    }
+
+    #[test]
+    fn saves_every_pet_under_its_own_name() {
+        let dir = std::env::temp_dir().join("hpp_saves_every_pet_under_its_own_name");
+        std::fs::remove_dir_all(&dir).ok();
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut hat = Hat::new(dir.clone());
+        for _ in 0..MAX_PETS {
+            let name_and_size = HatNameAndSize::new("walkingpet".to_string(), None);
+            let pet = WalkingPet::from_bitmap(Bitmap::with_size(64, 32), name_and_size).unwrap();
+            hat.add_element(pet);
+        }
+        hat.save(&dir).unwrap();
+
+        let hat = Hat::load(&dir).unwrap();
+        let mut names = hat
+            .pets
+            .iter()
+            .map(|p| p.base().name.clone().unwrap())
+            .collect::<Vec<_>>();
+        names.sort();
+        let expected = [
+            "walkingpet",
+            "walkingpet2",
+            "walkingpet3",
+            "walkingpet4",
+            "walkingpet5",
+        ];
+        assert_eq!(names, expected);
+        std::fs::remove_dir_all(dir).ok();
+    }
+
+    #[test]
+    fn saving_keeps_foreign_files_and_backs_up_old_ones() {
+        let dir = std::env::temp_dir().join("hpp_saving_keeps_foreign_files");
+        std::fs::remove_dir_all(&dir).ok();
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("notes.txt"), "keep me").unwrap();
+        std::fs::write(dir.join("hat.aseprite"), "keep me too").unwrap();
+        // named like an element, but not one the hat loaded
+        std::fs::write(dir.join("wings.png"), "not a png").unwrap();
+        Bitmap::with_size(32, 32)
+            .save(dir.join("extrahat.png"))
+            .unwrap();
+
+        let (mut hat, report) = Hat::load_with_report(&dir).unwrap();
+        let mut failed = report.failed_files.iter().map(|(path, _)| path);
+        assert!(failed.any(|path| path.ends_with("wings.png")));
+        let extra_id = hat.extra().unwrap().id();
+        hat.remove_element(extra_id);
+        let name_and_size = HatNameAndSize::new("hat".to_string(), None);
+        let wereable = Wereable::from_bitmap(Bitmap::with_size(32, 32), name_and_size).unwrap();
+        hat.add_element(wereable);
+        hat.save(&dir).unwrap();
+        hat.save(&dir).unwrap();
+
+        assert!(dir.join("notes.txt").exists());
+        assert!(dir.join("hat.aseprite").exists());
+        assert!(dir.join("wings.png").exists());
+        assert!(dir.join("hat_32_32.png").exists());
+        assert!(!dir.join("extrahat.png").exists());
+        assert!(dir.join(".bak").join("hat_32_32.png").exists());
+        assert!(dir.join(".bak.1").join("extrahat.png").exists());
+        assert!(!dir.join(".hpp_save").exists());
+        std::fs::remove_dir_all(dir).ok();
+    }
+
+    #[test]
+    fn saving_through_another_path_removes_old_files() {
+        let dir = std::env::temp_dir().join("hpp_saving_through_another_path");
+        std::fs::remove_dir_all(&dir).ok();
+        std::fs::create_dir_all(dir.join("sub")).unwrap();
+        Bitmap::with_size(32, 32)
+            .save(dir.join("extrahat.png"))
+            .unwrap();
+
+        let mut hat = Hat::load(dir.join("sub").join("..")).unwrap();
+        let extra_id = hat.extra().unwrap().id();
+        hat.remove_element(extra_id);
+        let name_and_size = HatNameAndSize::new("hat".to_string(), None);
+        let wereable = Wereable::from_bitmap(Bitmap::with_size(32, 32), name_and_size).unwrap();
+        hat.add_element(wereable);
+        hat.save(&dir).unwrap();
 
-        x += 1;
+        assert!(dir.join("hat_32_32.png").exists());
+        assert!(!dir.join("extrahat.png").exists());
+        std::fs::remove_dir_all(dir).ok();
     }
 }
@@ -0,0 +1,242 @@
+//! Undo and redo for a single hat.
+//!
+//! Instead of wrapping every edit in a command, the history keeps a snapshot of
+//! the hat and compares a hash of the live hat against it once a frame. Any
+//! difference, no matter which widget or menu made it, becomes one undo step.
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::Hasher;
+
+use crate::hats::Hat;
+
+/// Oldest steps are dropped past this, snapshots are not free.
+const MAX_STEPS: usize = 100;
+
+#[derive(Debug)]
+struct Snapshot {
+    hat: Hat,
+    /// Tells states apart, even ones that compare equal, like two different images.
+    version: u64,
+}
+
+#[derive(Debug)]
+pub struct HatHistory {
+    undo: Vec<Snapshot>,
+    redo: Vec<Snapshot>,
+    checkpoint: Snapshot,
+    checkpoint_key: u64,
+    force_commit: bool,
+    next_version: u64,
+    saved_version: u64,
+}
+
+impl HatHistory {
+    /// Starts the history at `hat`, which counts as saved.
+    pub fn new(hat: &Hat) -> Self {
+        Self {
+            undo: vec![],
+            redo: vec![],
+            checkpoint: Snapshot {
+                hat: hat.clone(),
+                version: 0,
+            },
+            checkpoint_key: state_key(hat),
+            force_commit: false,
+            next_version: 1,
+            saved_version: 0,
+        }
+    }
+
+    /// Makes the next [`HatHistory::commit`] record a step even if the hat looks the same,
+    /// for edits the comparison can not see, like swapping an element image.
+    pub fn mark_changed(&mut self) {
+        self.force_commit = true;
+    }
+
+    /// Remembers `hat` as the state that is on disk.
+    pub fn mark_saved(&mut self, hat: &Hat) {
+        self.commit(hat);
+        self.saved_version = self.checkpoint.version;
+    }
+
+    /// Treats the current state as never saved, for hats that did not come from their folder.
+    pub fn mark_unsaved(&mut self) {
+        self.saved_version = u64::MAX;
+    }
+
+    /// Whether the hat differs from what was last saved, as of the last commit.
+    pub fn is_modified(&self) -> bool {
+        self.checkpoint.version != self.saved_version
+    }
+
+    /// Records a step if the hat changed since the last call. Returns whether it did.
+    pub fn commit(&mut self, hat: &Hat) -> bool {
+        let key = state_key(hat);
+        if key == self.checkpoint_key && !self.force_commit {
+            return false;
+        }
+        let snapshot = Snapshot {
+            hat: hat.clone(),
+            version: self.next_version,
+        };
+        self.next_version += 1;
+        let previous = std::mem::replace(&mut self.checkpoint, snapshot);
+        self.checkpoint_key = key;
+        self.force_commit = false;
+        self.undo.push(previous);
+        if self.undo.len() > MAX_STEPS {
+            self.undo.remove(0);
+        }
+        self.redo.clear();
+        true
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    pub fn undo(&mut self, hat: &mut Hat) -> bool {
+        self.commit(hat);
+        let Some(previous) = self.undo.pop() else {
+            return false;
+        };
+        let current = self.restore(hat, previous);
+        self.redo.push(current);
+        true
+    }
+
+    pub fn redo(&mut self, hat: &mut Hat) -> bool {
+        self.commit(hat);
+        let Some(next) = self.redo.pop() else {
+            return false;
+        };
+        let current = self.restore(hat, next);
+        self.undo.push(current);
+        true
+    }
+
+    /// Every hat kept around for undo or redo, so their textures can be freed with the tab.
+    pub fn hats(&self) -> impl Iterator<Item = &Hat> {
+        self.undo
+            .iter()
+            .chain(self.redo.iter())
+            .chain(std::iter::once(&self.checkpoint))
+            .map(|snapshot| &snapshot.hat)
+    }
+
+    /// Puts `state` in place of `hat` and returns what was there. The path and files
+    /// are not edits, undoing past a "save as" should not move the hat back.
+    fn restore(&mut self, hat: &mut Hat, mut state: Snapshot) -> Snapshot {
+        state.hat.path = hat.path.clone();
+        state.hat.files = hat.files.clone();
+        let current = Snapshot {
+            hat: std::mem::replace(hat, state.hat),
+            version: self.checkpoint.version,
+        };
+        self.checkpoint = Snapshot {
+            hat: hat.clone(),
+            version: state.version,
+        };
+        self.checkpoint_key = state_key(hat);
+        self.force_commit = false;
+        current
+    }
+}
+
+/// Everything the user can edit, hashed. Images are left out, swapping one
+/// goes through [`HatHistory::mark_changed`].
+fn state_key(hat: &Hat) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    for element in hat.iter_all_elements() {
+        element.hash_state(&mut hasher);
+    }
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use crate::animations::{AnimType, Animation};
+    use crate::hats::{Hat, HatBase, HatType, Wereable};
+    use crate::history::HatHistory;
+
+    fn hat_with_wereable() -> Hat {
+        let mut hat = Hat::default();
+        let mut wereable = Wereable {
+            base: HatBase {
+                hat_type: HatType::Wereable,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let animation = Animation::new(AnimType::OnDefault, 4, false, vec![0.into()]);
+        wereable.animations.push(RefCell::new(animation).into());
+        hat.add_element(wereable);
+        hat
+    }
+
+    fn delay(hat: &Hat) -> i32 {
+        hat.wereable().unwrap().animations[0].borrow().delay
+    }
+
+    #[test]
+    fn undoes_and_redoes_animation_edits() {
+        let mut hat = hat_with_wereable();
+        let mut history = HatHistory::new(&hat);
+        assert!(!history.commit(&hat));
+
+        hat.wereable().unwrap().animations[0].borrow_mut().delay = 8;
+        assert!(history.commit(&hat));
+        hat.wereable_mut().unwrap().is_big = true;
+        assert!(history.commit(&hat));
+
+        assert!(history.undo(&mut hat));
+        assert!(!hat.wereable().unwrap().is_big);
+        assert_eq!(delay(&hat), 8);
+        assert!(history.undo(&mut hat));
+        assert_eq!(delay(&hat), 4);
+        assert!(!history.can_undo());
+
+        assert!(history.redo(&mut hat));
+        assert_eq!(delay(&hat), 8);
+        assert!(!history.commit(&hat));
+        assert!(history.can_redo());
+    }
+
+    #[test]
+    fn undoes_element_removal() {
+        let mut hat = hat_with_wereable();
+        let id = hat.wereable().unwrap().base.id;
+        let mut history = HatHistory::new(&hat);
+
+        hat.remove_element(id);
+        assert!(history.commit(&hat));
+        assert!(history.undo(&mut hat));
+        assert_eq!(hat.wereable().unwrap().base.id, id);
+        assert_eq!(delay(&hat), 4);
+    }
+
+    #[test]
+    fn tracks_changes_since_the_last_save() {
+        let mut hat = hat_with_wereable();
+        let mut history = HatHistory::new(&hat);
+
+        hat.wereable_mut().unwrap().is_big = true;
+        history.commit(&hat);
+        assert!(history.is_modified());
+        history.undo(&mut hat);
+        assert!(!history.is_modified());
+
+        history.redo(&mut hat);
+        history.mark_saved(&hat);
+        assert!(!history.is_modified());
+        history.mark_changed();
+        history.commit(&hat);
+        assert!(history.is_modified());
+    }
+}
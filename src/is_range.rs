@@ -1,14 +1,8 @@
 pub fn is_range(frames: &[i32]) -> bool {
-    for i in 0..(frames.len() - 1) {
-        let frame1 = frames[i];
-        let frame2 = frames[i + 1];
-
-        if (frame1 - frame2).abs() != 1 {
-            return false;
-        }
-    }
-
-    true
+    let Some(step) = frames.get(1).map(|frame| frame - frames[0]) else {
+        return true;
+    };
+    step.abs() == 1 && frames.windows(2).all(|pair| pair[1] - pair[0] == step)
 }
 
 #[cfg(test)]
@@ -27,4 +21,12 @@ mod tests {
     fn returns_true() {
         assert!(is_range(&[1, 2, 3]))
     }
+    #[test]
+    fn returns_false_back_and_forth() {
+        assert!(!is_range(&[1, 2, 1]))
+    }
+    #[test]
+    fn returns_true_empty() {
+        assert!(is_range(&[]))
+    }
 }
@@ -0,0 +1,30 @@
+//! Hat data layer shared by the editor and the command line tools.
+//!
+//! Everything in here decodes and encodes hats from plain [`pixas::bitmap::Bitmap`]s,
+//! so it works without an OpenGL context. GPU textures are attached afterwards by the
+//! editor through [`hats::HatBase::load_texture`].
+
+pub mod animation_export;
+pub mod animations;
+pub mod archive;
+pub mod aseprite;
+pub mod build;
+pub mod definition;
+pub mod file_utils;
+pub mod frame_import;
+pub mod frames_from_range;
+pub mod hat_utils;
+pub mod hats;
+pub mod history;
+pub mod is_range;
+pub mod metapixel_schema;
+pub mod metapixels;
+pub mod prelude;
+pub mod recovery;
+pub mod texture;
+pub mod texture_reloader;
+pub mod ui_text;
+pub mod validation;
+
+#[macro_use]
+extern crate num_derive;
@@ -3,33 +3,22 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")] // hide console window on Windows in release
 
 mod animation_window;
-mod animations;
 mod catppuccin_egui;
 mod colors;
 mod egui_utils;
 mod event_bus;
-mod file_utils;
-mod frames_from_range;
-mod hat_utils;
-mod hats;
 mod image_extensions;
-mod is_range;
-mod metapixels;
-mod prelude;
 mod renderer;
 mod shader;
 mod shader_reloader;
+mod sheet_grid;
+mod sheet_view;
 mod shortcuts;
 mod sprite;
 mod sprite_drawer;
 mod tabs;
-mod texture;
-mod texture_reloader;
-mod ui_text;
-
-#[macro_use]
-extern crate num_derive;
 
+use animations::AnimType;
 use anyhow::{bail, Result};
 use eframe::egui::{vec2, Button, Id, KeyboardShortcut, ViewportBuilder};
 use eframe::glow::NativeBuffer;
@@ -39,21 +28,30 @@ use eframe::{
     glow::{Context, HasContext, NativeVertexArray},
     NativeOptions,
 };
+use egui_dock::{NodeIndex, SurfaceIndex, TabIndex};
 use file_utils::FileStemString;
-use hats::{AbstractHat, Extra, FlyingPet, Hat, LoadHat, Preview, WalkingPet, Wereable, Wings};
-use renderer::{Renderer, ScreenUpdate};
+use hats::{AbstractHat, Extra, FlyingPet, Hat, Preview, RoomHat, WalkingPet, Wereable, Wings};
+use hats_plus_plus_editor::{
+    animation_export, animations, archive, aseprite, build, definition, file_utils, frame_import,
+    frames_from_range, hat_utils, hats, history, metapixels, prelude, recovery, texture,
+    texture_reloader, ui_text, validation,
+};
 use serde::{Deserialize, Serialize};
 use shader::Shader;
 use shader_reloader::ShaderReloader;
 use std::path::{Path, PathBuf};
 use std::rc::Rc;
 use std::sync::RwLock;
-use std::time::SystemTime;
+use std::time::{Duration, SystemTime};
 use tabs::{FrameData, HelpTabData, Tab, TabType, Tabs};
 use texture_reloader::TextureReloader;
 use ui_text::{Language, UiText};
 
 const HERTZ_MAGIC_NUMBER: f32 = 1.5;
+const APP_NAME: &str = "Editor";
+/// Folder under the eframe storage location with snapshots of unsaved hats.
+const RECOVERY_DIR: &str = "recovery";
+const AUTOSAVE_INTERVAL: Duration = Duration::from_secs(30);
 pub static VERTEX_BUFFER: RwLock<Option<NativeBuffer>> = RwLock::new(None);
 pub static VERTEX_ARRAY: RwLock<Option<NativeVertexArray>> = RwLock::new(None);
 
@@ -85,6 +83,8 @@ pub struct AppConfig {
     pub language: Language,
     pub theme: Theme,
     pub latest_hats: Vec<PathBuf>,
+    #[serde(default)]
+    pub animation_export: animation_export::ExportOptions,
 }
 
 impl AppConfig {
@@ -129,6 +129,28 @@ struct MyEguiApp {
     current_hertz: f32,
     hertz_timer: f32,
     help_tab_data: Option<HelpTabData>,
+    /// The window was asked to close while some hats had unsaved changes.
+    closing_app: bool,
+    allow_close: bool,
+    recovery_dir: Option<PathBuf>,
+    /// Snapshots left over from the last run, with what they change, until the user decides.
+    recovered_hats: Vec<(recovery::RecoveredHat, Vec<String>)>,
+    last_autosave: SystemTime,
+    /// Whether zip exports get a manifest listing the elements.
+    zip_manifest: bool,
+    /// New elements are made from a folder of frames instead of a single image.
+    from_frame_folder: bool,
+    /// The animation elements made from frames get.
+    frames_anim_type: AnimType,
+    /// Failures shown to the user until they dismiss them.
+    errors: Vec<String>,
+}
+
+/// What to do with unsaved changes of something being closed.
+enum CloseChoice {
+    Save,
+    Discard,
+    Cancel,
 }
 
 impl MyEguiApp {
@@ -167,6 +189,25 @@ impl MyEguiApp {
         if ui.shortcut_pressed(shortcuts::SAVE_AS) {
             self.save_hat_as();
         }
+        // redo first, it is the same keys plus shift
+        if ui.shortcut_pressed(shortcuts::REDO) {
+            self.redo();
+        }
+        if ui.shortcut_pressed(shortcuts::UNDO) {
+            self.undo();
+        }
+    }
+
+    fn undo(&mut self) -> Option<()> {
+        let last_tab = self.last_interacted_tab_mut()?;
+        last_tab.inner.borrow_mut().undo();
+        Some(())
+    }
+
+    fn redo(&mut self) -> Option<()> {
+        let last_tab = self.last_interacted_tab_mut()?;
+        last_tab.inner.borrow_mut().redo();
+        Some(())
     }
 
     fn draw_hat_menu(&mut self, ctx: &egui::Context, gl: &Context, ui: &mut Ui) {
@@ -178,6 +219,12 @@ impl MyEguiApp {
         let has_elements = last_tab
             .map(|tab| tab.inner.borrow().hat.has_elements())
             .unwrap_or(false);
+        let can_undo = last_tab
+            .map(|tab| tab.inner.borrow().history.can_undo())
+            .unwrap_or(false);
+        let can_redo = last_tab
+            .map(|tab| tab.inner.borrow().history.can_redo())
+            .unwrap_or(false);
         ui.menu_button(text.get("Hat"), |ui| {
             if ui
                 .add(self.button_shortcut(ctx, &text.get("New1"), shortcuts::NEW))
@@ -191,6 +238,9 @@ impl MyEguiApp {
             {
                 let _ = self.open_hat_with_dialog(gl);
                 ui.close_menu();
+            } else if ui.button(text.get("Open zip")).clicked() {
+                let _ = self.open_zip_with_dialog(gl);
+                ui.close_menu();
             } else if ui
                 .add_enabled(
                     has_path,
@@ -209,7 +259,69 @@ impl MyEguiApp {
             {
                 self.save_hat_as();
                 ui.close_menu()
+            } else if ui
+                .add_enabled(has_elements, Button::new(text.get("Export as zip")))
+                .clicked()
+            {
+                self.export_zip();
+                ui.close_menu();
+            } else if ui
+                .add_enabled(has_elements, Button::new(text.get("Export definition")))
+                .clicked()
+            {
+                self.export_definition();
+                ui.close_menu();
+            } else if ui
+                .add_enabled(has_elements, Button::new(text.get("Import definition")))
+                .clicked()
+            {
+                self.import_definition();
+                ui.close_menu();
+            } else if ui
+                .add_enabled(
+                    can_undo,
+                    self.button_shortcut(ctx, &text.get("Undo"), shortcuts::UNDO),
+                )
+                .clicked()
+            {
+                self.undo();
+                ui.close_menu();
+            } else if ui
+                .add_enabled(
+                    can_redo,
+                    self.button_shortcut(ctx, &text.get("Redo"), shortcuts::REDO),
+                )
+                .clicked()
+            {
+                self.redo();
+                ui.close_menu();
             }
+            let can_rebuild = self
+                .last_interacted_tab()
+                .and_then(|tab| tab.inner.borrow().hat.path.clone())
+                .is_some_and(|path| build::find_manifest(&path).is_some());
+            if ui
+                .add_enabled(can_rebuild, Button::new(text.get("Rebuild")))
+                .clicked()
+            {
+                let last_tab = self.last_interacted_tab();
+                match last_tab.filter(|tab| tab.inner.borrow().is_modified()) {
+                    Some(tab) => tab.inner.borrow_mut().rebuild_requested = true,
+                    None => self.rebuild_hat_or_report(gl),
+                }
+                ui.close_menu();
+            }
+            let is_hat_tab = self
+                .last_interacted_tab()
+                .is_some_and(|tab| matches!(tab.inner.borrow().tab_type, TabType::Regular));
+            if ui
+                .add_enabled(is_hat_tab, Button::new(text.get("Sheet view")))
+                .clicked()
+            {
+                self.open_sheet_view();
+                ui.close_menu();
+            }
+            ui.checkbox(&mut self.zip_manifest, text.get("Include manifest"));
             ui.collapsing(text.get("Recent"), |ui| {
                 let mut recent_hat_path = None;
                 for path in self.config.latest_hats.iter().rev() {
@@ -259,27 +371,47 @@ impl MyEguiApp {
 
     fn draw_elements_menu(&mut self, ui: &mut Ui, gl: &Context) {
         let mut new_texture = None;
+        let mut add_error = None;
+        let mut from_frame_folder = self.from_frame_folder;
+        let mut frames_anim_type = self.frames_anim_type;
         {
             let text = self.ui_text.clone();
             let Some(last_tab) = self.last_interacted_tab_mut() else {
                 return;
             };
             let mut inner = last_tab.inner.borrow_mut();
+            let pick_path = |from_folder| match from_folder {
+                true => rfd::FileDialog::new().pick_folder(),
+                false => rfd::FileDialog::new().pick_file(),
+            };
+            let failed_to_add = |path: &Path, err: anyhow::Error| {
+                format!("{} {}: {err:#}", text.get("Could not add"), path.display())
+            };
             // no FUCKING way this works
             macro_rules! try_add_hat {
                 ($ui:ident, $get_hat:ident, $hat_type:ident) => {
+                    try_add_hat!($ui, $get_hat, $hat_type, $hat_type)
+                };
+                ($ui:ident, $get_hat:ident, $hat_type:ident, $element:ident) => {
                     if inner.hat.$get_hat().is_none()
                         && $ui
                             .button(hats::HatType::$hat_type.get_display_name(&text))
                             .clicked()
                     {
-                        if let Some(path) = rfd::FileDialog::new().pick_file() {
-                            if let Ok(hat) = $hat_type::load_from_path(path, gl) {
-                                new_texture = Some(hat.texture().unwrap().clone());
-                                inner
-                                    .hat
-                                    .add_unique_hat(hats::HatType::$hat_type, Box::new(hat));
-                                $ui.close_menu();
+                        if let Some(path) = pick_path(from_frame_folder) {
+                            let added = tabs::add_element_from::<$element>(
+                                &mut inner.hat,
+                                &path,
+                                from_frame_folder,
+                                frames_anim_type,
+                                gl,
+                            );
+                            match added {
+                                Ok(texture) => {
+                                    new_texture = Some(texture);
+                                    $ui.close_menu();
+                                }
+                                Err(err) => add_error = Some(failed_to_add(&path, err)),
                             }
                         }
                     }
@@ -287,10 +419,23 @@ impl MyEguiApp {
             }
             ui.add_enabled_ui(matches!(inner.tab_type, TabType::Regular), |ui| {
                 ui.collapsing(text.get("Add"), |ui| {
+                    ui.checkbox(&mut from_frame_folder, text.get("From frame folder"));
+                    egui::ComboBox::from_label(text.get("Frames animation"))
+                        .selected_text(frames_anim_type.to_string())
+                        .show_ui(ui, |ui| {
+                            for anim_type in AnimType::all() {
+                                ui.selectable_value(
+                                    &mut frames_anim_type,
+                                    anim_type,
+                                    anim_type.to_string(),
+                                );
+                            }
+                        });
                     try_add_hat!(ui, wereable, Wereable);
                     try_add_hat!(ui, wings, Wings);
                     try_add_hat!(ui, extra, Extra);
                     try_add_hat!(ui, preview, Preview);
+                    try_add_hat!(ui, room, Room, RoomHat);
                     if inner.hat.preview().is_none()
                         && ui
                             .add_enabled(
@@ -299,11 +444,20 @@ impl MyEguiApp {
                             )
                             .clicked()
                     {
-                        if let Some(path) = rfd::FileDialog::new().pick_file() {
-                            if let Ok(hat) = WalkingPet::load_from_path(path, gl) {
-                                new_texture = Some(hat.texture().unwrap().clone());
-                                inner.hat.add_pet(Box::new(hat));
-                                ui.close_menu();
+                        if let Some(path) = pick_path(from_frame_folder) {
+                            let added = tabs::add_element_from::<WalkingPet>(
+                                &mut inner.hat,
+                                &path,
+                                from_frame_folder,
+                                frames_anim_type,
+                                gl,
+                            );
+                            match added {
+                                Ok(texture) => {
+                                    new_texture = Some(texture);
+                                    ui.close_menu();
+                                }
+                                Err(err) => add_error = Some(failed_to_add(&path, err)),
                             }
                         }
                     }
@@ -315,11 +469,20 @@ impl MyEguiApp {
                             )
                             .clicked()
                     {
-                        if let Some(path) = rfd::FileDialog::new().pick_file() {
-                            if let Ok(hat) = FlyingPet::load_from_path(path, gl) {
-                                new_texture = Some(hat.texture().unwrap().clone());
-                                inner.hat.add_pet(Box::new(hat));
-                                ui.close_menu();
+                        if let Some(path) = pick_path(from_frame_folder) {
+                            let added = tabs::add_element_from::<FlyingPet>(
+                                &mut inner.hat,
+                                &path,
+                                from_frame_folder,
+                                frames_anim_type,
+                                gl,
+                            );
+                            match added {
+                                Ok(texture) => {
+                                    new_texture = Some(texture);
+                                    ui.close_menu();
+                                }
+                                Err(err) => add_error = Some(failed_to_add(&path, err)),
                             }
                         }
                     }
@@ -341,8 +504,9 @@ impl MyEguiApp {
                         for pet in inner.hat.pets.iter() {
                             let size = pet.base().hat_area_size;
                             let button_name = format!(
-                                "{0} ({1}, {2})",
+                                "{0} {1} ({2}, {3})",
                                 pet.base().hat_type.get_display_name(text.as_ref()),
+                                pet.base().name.as_deref().unwrap_or_default(),
                                 size.x,
                                 size.y
                             );
@@ -359,6 +523,26 @@ impl MyEguiApp {
         if let Some(texture) = new_texture {
             self.texture_reloader.add_texture(&texture);
         }
+        self.errors.extend(add_error);
+        self.from_frame_folder = from_frame_folder;
+        self.frames_anim_type = frames_anim_type;
+    }
+
+    /// Opens a view of the whole image of the element selected in the last tab,
+    /// next to the other tabs.
+    fn open_sheet_view(&mut self) {
+        let name = self.ui_text.get("Sheet view");
+        let Some(sheet_tab) = self.last_interacted_tab().map(|tab| {
+            let inner = tab.inner.borrow();
+            Tab::new_sheet(format!("{}: {name}", inner.title), &inner.sheet_source)
+        }) else {
+            return;
+        };
+        self.tabs.dock_state.main_surface_mut().split_right(
+            NodeIndex::root(),
+            0.6,
+            vec![sheet_tab],
+        );
     }
 
     fn last_interacted_tab(&mut self) -> Option<&Tab> {
@@ -383,6 +567,108 @@ impl MyEguiApp {
         self.open_hat(gl, &path)
     }
 
+    fn open_zip_with_dialog(&mut self, gl: &Context) -> Result<()> {
+        let dialog = rfd::FileDialog::new().add_filter("zip", &["zip"]);
+        let Some(path) = dialog.pick_file() else {
+            bail!("coud not pick file");
+        };
+        self.open_hat(gl, &path)
+    }
+
+    fn export_zip(&mut self) -> Option<()> {
+        if !self.can_save_last_tab() {
+            return None;
+        }
+        let with_manifest = self.zip_manifest;
+        let result = {
+            let last_tab = self.last_interacted_tab()?;
+            let inner = last_tab.inner.borrow();
+            let path = rfd::FileDialog::new()
+                .add_filter("zip", &["zip"])
+                .set_file_name(format!("{}.zip", inner.title))
+                .save_file()?;
+            archive::save_zip(&inner.hat, &path, with_manifest)
+                .map_err(|err| format!("{}: {err:#}", inner.title))
+        };
+        if let Err(err) = result {
+            let message = self.ui_text.get("Could not export");
+            self.errors.push(format!("{message} {err}"));
+            return None;
+        }
+        Some(())
+    }
+
+    fn export_definition(&mut self) -> Option<()> {
+        let result = {
+            let last_tab = self.last_interacted_tab()?;
+            let inner = last_tab.inner.borrow();
+            let path = rfd::FileDialog::new()
+                .add_filter("json", &["json"])
+                .add_filter("toml", &["toml"])
+                .set_file_name(format!("{}.json", inner.title))
+                .save_file()?;
+            definition::write_definition(&inner.hat, &path)
+                .map_err(|err| format!("{}: {err:#}", inner.title))
+        };
+        if let Err(err) = result {
+            let message = self.ui_text.get("Could not export");
+            self.errors.push(format!("{message} {err}"));
+            return None;
+        }
+        Some(())
+    }
+
+    /// Applies a definition to the last tab, it becomes a regular undo step.
+    fn import_definition(&mut self) -> Option<()> {
+        let path = rfd::FileDialog::new()
+            .add_filter("definition", &["json", "toml"])
+            .pick_file()?;
+        let result = {
+            let last_tab = self.last_interacted_tab_mut()?;
+            let inner = &mut *last_tab.inner.borrow_mut();
+            definition::read_definition(&path)
+                .and_then(|d| definition::apply_definition(&mut inner.hat, &d))
+        };
+        if let Err(err) = result {
+            let message = self.ui_text.get("Could not import");
+            self.errors
+                .push(format!("{message} {}: {err:#}", path.display()));
+            return None;
+        }
+        Some(())
+    }
+
+    fn rebuild_hat_or_report(&mut self, gl: &Context) {
+        if let Err(err) = self.rebuild_hat(gl) {
+            let message = self.ui_text.get("Could not rebuild");
+            self.errors.push(format!("{message} {err:#}"));
+        }
+    }
+
+    /// Builds the last tab's hat again from its frames and manifest. The rebuilt hat
+    /// is written over the saved one and replaces the hat in the tab as saved, so the
+    /// menu asks about unsaved changes first.
+    fn rebuild_hat(&mut self, gl: &Context) -> Result<()> {
+        let Some(manifest) = self
+            .last_interacted_tab()
+            .and_then(|tab| tab.inner.borrow().hat.path.clone())
+            .and_then(|path| build::find_manifest(&path))
+        else {
+            bail!("the hat has no build manifest");
+        };
+        let mut hat = build::build_hat(&manifest)?;
+        hat.load_textures(gl)?;
+        hat.add_textures_to_reloader(&mut self.texture_reloader);
+        let Some(last_tab) = self.last_interacted_tab_mut() else {
+            bail!("the hat tab is gone");
+        };
+        let inner = &mut *last_tab.inner.borrow_mut();
+        inner.selected_hat_id = hat.iter_all_elements().next().map(|h| h.id());
+        inner.hat = hat;
+        inner.history.mark_saved(&inner.hat);
+        Ok(())
+    }
+
     fn open_hat(&mut self, gl: &Context, dir_path: impl AsRef<Path>) -> Result<()> {
         if self.tabs.dock_state.iter_all_tabs().any(|t| {
             t.1.inner
@@ -395,12 +681,20 @@ impl MyEguiApp {
         }) {
             bail!("hat with the same path is already opened");
         }
-        let hat = Hat::load(dir_path, gl)?;
+        let (mut hat, report) = archive::load_hat(dir_path)?;
+        if !report.is_empty() {
+            let message = self.ui_text.get("Loading problems");
+            self.errors.push(format!("{message}\n{report}"));
+        }
+        hat.load_textures(gl)?;
         let selected_hat_id = hat.iter_all_elements().next().map(|h| h.id());
         let name = match hat
             .path
             .as_ref()
-            .and_then(|p| p.file_name())
+            .and_then(|p| match archive::is_zip(p) {
+                true => p.file_stem(),
+                false => p.file_name(),
+            })
             .map(|p| p.to_owned())
             .map(|p| p.to_string_lossy().to_string())
         {
@@ -409,15 +703,7 @@ impl MyEguiApp {
         };
         hat.add_textures_to_reloader(&mut self.texture_reloader);
         let tab = Tab::new(name, hat);
-        let mut inner = tab.inner.borrow_mut();
-        if inner.hat.room().is_some() {
-            inner.renderer = Some(Renderer::new(
-                renderer::RENDERER_SCREEN_SIZE,
-                ScreenUpdate::Clear,
-            ));
-        }
-        inner.selected_hat_id = selected_hat_id;
-        drop(inner);
+        tab.inner.borrow_mut().selected_hat_id = selected_hat_id;
         self.tabs.dock_state.push_to_focused_leaf(tab);
         Ok(())
     }
@@ -428,22 +714,349 @@ impl MyEguiApp {
         name
     }
 
+    /// Saving is refused while the hat has validation errors, they are listed in the tab.
+    fn can_save_last_tab(&mut self) -> bool {
+        let title = {
+            let Some(last_tab) = self.last_interacted_tab() else {
+                return false;
+            };
+            let inner = last_tab.inner.borrow();
+            if !matches!(inner.tab_type, TabType::Regular) {
+                return false;
+            }
+            let findings = validation::validate_hat(&inner.hat);
+            if !validation::has_errors(&findings) {
+                return true;
+            }
+            inner.title.clone()
+        };
+        let message = self.ui_text.get("Has errors");
+        self.errors.push(format!("{title}: {message}"));
+        false
+    }
+
     fn save_hat_as(&mut self) -> Option<()> {
-        let dir_path = rfd::FileDialog::new().pick_folder()?;
-        let last_tab = self.last_interacted_tab_mut()?;
-        let mut inner = last_tab.inner.borrow_mut();
-        if !matches!(inner.tab_type, TabType::Regular) {
+        if !self.can_save_last_tab() {
             return None;
         }
-        inner.hat.path = Some(dir_path.clone());
-        let result = inner.hat.save(&dir_path);
+        let dir_path = rfd::FileDialog::new().pick_folder()?;
+        self.save_last_tab_to(&dir_path)?;
+        let last_tab = self.last_interacted_tab_mut()?;
+        let inner = &mut *last_tab.inner.borrow_mut();
         inner.title = dir_path.file_stem_string().unwrap();
-        result.ok()
+        Some(())
     }
+
     fn save_hat(&mut self) -> Option<()> {
-        let last_tab = self.last_interacted_tab_mut()?;
-        let inner = &mut last_tab.inner.borrow_mut();
-        inner.hat.save(inner.hat.path.as_ref()?).ok()
+        if !self.can_save_last_tab() {
+            return None;
+        }
+        let path = self
+            .last_interacted_tab()?
+            .inner
+            .borrow()
+            .hat
+            .path
+            .clone()?;
+        self.save_last_tab_to(&path)
+    }
+
+    /// The hat only takes the new path once it was saved there.
+    fn save_last_tab_to(&mut self, path: &Path) -> Option<()> {
+        let result = {
+            let last_tab = self.last_interacted_tab_mut()?;
+            let inner = &mut *last_tab.inner.borrow_mut();
+            let result = archive::save_hat(&mut inner.hat, path);
+            if result.is_ok() {
+                inner.hat.path = Some(path.to_owned());
+                inner.history.mark_saved(&inner.hat);
+            }
+            result.map_err(|err| format!("{}: {err:#}", inner.title))
+        };
+        if let Err(err) = result {
+            let message = self.ui_text.get("Could not save");
+            self.errors.push(format!("{message} {err}"));
+            return None;
+        }
+        Some(())
+    }
+
+    fn save_or_save_as(&mut self) -> Option<()> {
+        let has_path = self
+            .last_interacted_tab()
+            .is_some_and(|tab| tab.inner.borrow().hat.path.is_some());
+        if has_path {
+            self.save_hat()
+        } else {
+            self.save_hat_as()
+        }
+    }
+
+    fn focus_tab(&mut self, index: (SurfaceIndex, NodeIndex, TabIndex)) {
+        let dock_state = &mut self.tabs.dock_state;
+        dock_state.set_active_tab(index);
+        dock_state.set_focused_node_and_surface((index.0, index.1));
+    }
+
+    fn close_tab(&mut self, index: (SurfaceIndex, NodeIndex, TabIndex), gl: &Context) {
+        if let Some(tab) = self.tabs.dock_state.remove_tab(index) {
+            let latest_hats = &mut Rc::get_mut(&mut self.config).unwrap().latest_hats;
+            tab.inner.borrow_mut().release(gl, latest_hats);
+        }
+    }
+
+    fn has_modified_tabs(&mut self) -> bool {
+        self.tabs
+            .dock_state
+            .iter_all_tabs()
+            .any(|(_, tab)| tab.inner.borrow().is_modified())
+    }
+
+    /// Saves every modified hat, stops at the first one that could not be saved.
+    fn save_modified_tabs(&mut self) -> bool {
+        loop {
+            let Some(index) = self
+                .tabs
+                .dock_state
+                .find_tab_from(|tab| tab.inner.borrow().is_modified())
+            else {
+                return true;
+            };
+            self.focus_tab(index);
+            if self.save_or_save_as().is_none() {
+                return false;
+            }
+        }
+    }
+
+    fn close_prompt_ui(&self, ctx: &egui::Context, message: &str) -> Option<CloseChoice> {
+        let text = &self.ui_text;
+        let mut choice = None;
+        egui::Window::new(text.get("Unsaved changes"))
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, vec2(0.0, 0.0))
+            .show(ctx, |ui| {
+                ui.label(message);
+                ui.horizontal(|ui| {
+                    if ui.button(text.get("Save")).clicked() {
+                        choice = Some(CloseChoice::Save);
+                    }
+                    if ui.button(text.get("Discard")).clicked() {
+                        choice = Some(CloseChoice::Discard);
+                    }
+                    if ui.button(text.get("Cancel")).clicked() {
+                        choice = Some(CloseChoice::Cancel);
+                    }
+                });
+            });
+        choice
+    }
+
+    /// Writes a snapshot of every modified hat, so a crash does not lose them.
+    fn autosave(&mut self) {
+        let Some(dir) = self.recovery_dir.clone() else {
+            return;
+        };
+        let hats = self
+            .tabs
+            .dock_state
+            .iter_all_tabs()
+            .filter_map(|(_, tab)| {
+                let inner = tab.inner.borrow();
+                if !matches!(inner.tab_type, TabType::Regular) || !inner.is_modified() {
+                    return None;
+                }
+                let info = recovery::RecoveryInfo {
+                    title: inner.title.clone(),
+                    path: inner.hat.path.clone(),
+                };
+                Some((info, inner.hat.clone()))
+            })
+            .collect::<Vec<_>>();
+        let result = if hats.is_empty() {
+            recovery::clear_snapshots(&dir)
+        } else {
+            recovery::write_snapshots(&dir, hats.iter().map(|(info, hat)| (info.clone(), hat)))
+        };
+        if let Err(err) = result {
+            let message = format!("{} {err:#}", self.ui_text.get("Could not autosave"));
+            // autosave runs again soon, the same failure is shown once
+            if !self.errors.contains(&message) {
+                self.errors.push(message);
+            }
+        }
+    }
+
+    fn restore_hats(&mut self, gl: &Context) {
+        for (recovered, _) in std::mem::take(&mut self.recovered_hats) {
+            let result: Result<()> = try {
+                let mut hat = recovered.load()?;
+                hat.load_textures(gl)?;
+                hat.add_textures_to_reloader(&mut self.texture_reloader);
+                let selected_hat_id = hat.iter_all_elements().next().map(|h| h.id());
+                let tab = Tab::new(recovered.info.title.clone(), hat);
+                {
+                    let mut inner = tab.inner.borrow_mut();
+                    inner.selected_hat_id = selected_hat_id;
+                    inner.history.mark_unsaved();
+                }
+                self.tabs.dock_state.push_to_focused_leaf(tab);
+            };
+            if let Err(err) = result {
+                let message = self.ui_text.get("Could not restore");
+                self.errors
+                    .push(format!("{message} {}: {err:#}", recovered.info.title));
+            }
+        }
+    }
+
+    /// Offers to bring back the hats that were not saved when the editor last closed.
+    fn draw_recovery_prompt(&mut self, ctx: &egui::Context, gl: &Context) {
+        if self.recovered_hats.is_empty() {
+            return;
+        }
+        let text = self.ui_text.clone();
+        let mut restore = None;
+        egui::Window::new(text.get("Recover hats"))
+            .collapsible(false)
+            .anchor(egui::Align2::CENTER_CENTER, vec2(0.0, 0.0))
+            .show(ctx, |ui| {
+                ui.label(text.get("Recover text"));
+                for (hat, changes) in &self.recovered_hats {
+                    let path = hat.info.path.as_ref().map(|p| p.to_string_lossy());
+                    let title = format!("{} {}", hat.info.title, path.unwrap_or_default());
+                    egui::CollapsingHeader::new(title)
+                        .id_source(&hat.dir)
+                        .show(ui, |ui| {
+                            if changes.is_empty() {
+                                ui.label(text.get("No changes"));
+                            }
+                            for change in changes {
+                                ui.label(change);
+                            }
+                        });
+                }
+                ui.horizontal(|ui| {
+                    if ui.button(text.get("Restore")).clicked() {
+                        restore = Some(true);
+                    }
+                    if ui.button(text.get("Discard")).clicked() {
+                        restore = Some(false);
+                    }
+                });
+            });
+        match restore {
+            Some(true) => self.restore_hats(gl),
+            Some(false) => {
+                self.recovered_hats.clear();
+                if let Some(dir) = &self.recovery_dir {
+                    recovery::clear_snapshots(dir).ok();
+                }
+            }
+            None => {}
+        }
+    }
+
+    /// Asks what to do with unsaved changes of a tab before it is rebuilt.
+    fn draw_rebuild_prompt(&mut self, ctx: &egui::Context, gl: &Context) {
+        let dock_state = &self.tabs.dock_state;
+        let Some(index) = dock_state.find_tab_from(|tab| tab.inner.borrow().rebuild_requested)
+        else {
+            return;
+        };
+        let title = dock_state
+            .iter_all_tabs()
+            .find(|(_, tab)| tab.inner.borrow().rebuild_requested)
+            .map(|(_, tab)| tab.inner.borrow().title.clone())
+            .unwrap_or_default();
+        let message = format!("{} {title}?", self.ui_text.get("Unsaved hat"));
+        let Some(choice) = self.close_prompt_ui(ctx, &message) else {
+            return;
+        };
+        for (_, tab) in self.tabs.dock_state.iter_all_tabs_mut() {
+            tab.inner.borrow_mut().rebuild_requested = false;
+        }
+        self.focus_tab(index);
+        match choice {
+            CloseChoice::Save => {
+                if self.save_or_save_as().is_some() {
+                    self.rebuild_hat_or_report(gl);
+                }
+            }
+            CloseChoice::Discard => self.rebuild_hat_or_report(gl),
+            CloseChoice::Cancel => {}
+        }
+    }
+
+    /// Lists what went wrong, until the user dismisses it.
+    fn draw_errors(&mut self, ctx: &egui::Context) {
+        if self.errors.is_empty() {
+            return;
+        }
+        let text = &self.ui_text;
+        let mut dismissed = false;
+        egui::Window::new(text.get("Error"))
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, vec2(0.0, 0.0))
+            .show(ctx, |ui| {
+                for error in &self.errors {
+                    ui.label(error);
+                }
+                dismissed = ui.button(text.get("OK")).clicked();
+            });
+        if dismissed {
+            self.errors.clear();
+        }
+    }
+
+    /// Asks what to do with unsaved changes of a closed tab or of the whole app.
+    fn draw_close_prompts(&mut self, ctx: &egui::Context, gl: &Context) {
+        if self.closing_app {
+            let message = self.ui_text.get("Unsaved hats");
+            match self.close_prompt_ui(ctx, &message) {
+                Some(CloseChoice::Save) => {
+                    if self.save_modified_tabs() {
+                        self.allow_close = true;
+                        ctx.send_viewport_cmd(egui::ViewportCommand::Close);
+                    }
+                }
+                Some(CloseChoice::Discard) => {
+                    self.allow_close = true;
+                    ctx.send_viewport_cmd(egui::ViewportCommand::Close);
+                }
+                Some(CloseChoice::Cancel) => self.closing_app = false,
+                None => {}
+            }
+            return;
+        }
+        let dock_state = &self.tabs.dock_state;
+        let Some(index) = dock_state.find_tab_from(|tab| tab.inner.borrow().close_requested) else {
+            return;
+        };
+        let title = dock_state
+            .iter_all_tabs()
+            .find(|(_, tab)| tab.inner.borrow().close_requested)
+            .map(|(_, tab)| tab.inner.borrow().title.clone())
+            .unwrap_or_default();
+        let message = format!("{} {title}?", self.ui_text.get("Unsaved hat"));
+        match self.close_prompt_ui(ctx, &message) {
+            Some(CloseChoice::Save) => {
+                self.focus_tab(index);
+                if self.save_or_save_as().is_some() {
+                    self.close_tab(index, gl);
+                }
+            }
+            Some(CloseChoice::Discard) => self.close_tab(index, gl),
+            Some(CloseChoice::Cancel) => {
+                let dock_state = &mut self.tabs.dock_state;
+                for (_, tab) in dock_state.iter_all_tabs_mut() {
+                    tab.inner.borrow_mut().close_requested = false;
+                }
+            }
+            None => {}
+        }
     }
 
     fn button_shortcut(
@@ -503,6 +1116,7 @@ impl MyEguiApp {
                     language: Language::English,
                     theme: Theme::Mocha,
                     latest_hats: vec![],
+                    animation_export: Default::default(),
                 };
                 cc.egui_ctx.memory_mut(|memory| {
                     memory.data.insert_persisted(Id::NULL, config.clone());
@@ -539,6 +1153,17 @@ impl MyEguiApp {
         shader_reloader.add_shader(&animation_shader);
         let ui_text: Rc<UiText> = UiText::new(language, include_str!("../text.json")).into();
         let home_name = ui_text.get("Home");
+        let recovery_dir = eframe::storage_dir(APP_NAME).map(|dir| dir.join(RECOVERY_DIR));
+        let recovered_hats = recovery_dir
+            .as_deref()
+            .map(recovery::read_snapshots)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|hat| {
+                let changes = hat.changes().unwrap_or_else(|err| vec![format!("{err:#}")]);
+                (hat, changes)
+            })
+            .collect();
         MyEguiApp::init_opengl_objects(gl);
         Self {
             hertz_timer: 0.0,
@@ -554,6 +1179,15 @@ impl MyEguiApp {
             last_time: SystemTime::now(),
             current_time: SystemTime::now(),
             help_tab_data: None,
+            closing_app: false,
+            allow_close: false,
+            recovery_dir,
+            recovered_hats,
+            last_autosave: SystemTime::now(),
+            zip_manifest: true,
+            from_frame_folder: false,
+            frames_anim_type: AnimType::OnDefault,
+            errors: vec![],
         }
     }
 
@@ -595,7 +1229,8 @@ impl MyEguiApp {
         }
     }
 
-    fn on_close(&mut self, ctx: &egui::Context) {
+    /// Returns false when the app has to stay open to show what went wrong.
+    fn on_close(&mut self, ctx: &egui::Context) -> bool {
         {
             let config = Rc::get_mut(&mut self.config).unwrap();
             for (_, tab) in self.tabs.dock_state.iter_all_tabs() {
@@ -608,11 +1243,24 @@ impl MyEguiApp {
             }
             config.remove_invalid_latest();
         }
+        // left over snapshots the user has not decided on yet are kept for the next run
+        let cleared = match &self.recovery_dir {
+            Some(dir) if self.recovered_hats.is_empty() => recovery::clear_snapshots(dir),
+            _ => Ok(()),
+        };
         ctx.memory_mut(|memory| {
             memory
                 .data
                 .insert_persisted(Id::NULL, (*self.config).clone());
         });
+        if let Err(err) = cleared {
+            // the snapshots are offered again on the next run, closing again does not retry
+            self.recovery_dir = None;
+            let message = self.ui_text.get("Could not clear snapshots");
+            self.errors.push(format!("{message} {err:#}"));
+            return false;
+        }
+        true
     }
 
     fn pre_update(&mut self, ctx: &egui::Context, gl: &Context) {
@@ -676,7 +1324,16 @@ impl MyEguiApp {
 impl eframe::App for MyEguiApp {
     fn update(&mut self, ctx: &egui::Context, frame: &mut eframe::Frame) {
         if ctx.input(|i| i.viewport().close_requested()) {
-            self.on_close(ctx);
+            if self.allow_close || !self.has_modified_tabs() {
+                if !self.on_close(ctx) {
+                    ctx.send_viewport_cmd(egui::ViewportCommand::CancelClose);
+                    self.closing_app = false;
+                    self.allow_close = false;
+                }
+            } else {
+                ctx.send_viewport_cmd(egui::ViewportCommand::CancelClose);
+                self.closing_app = true;
+            }
         }
         let gl = frame.gl().unwrap().as_ref();
         self.pre_update(ctx, gl);
@@ -716,6 +1373,19 @@ impl eframe::App for MyEguiApp {
                 };
             }
         });
+        self.draw_close_prompts(ctx, gl);
+        self.draw_rebuild_prompt(ctx, gl);
+        self.draw_recovery_prompt(ctx, gl);
+        while let Some(error) = tabs::ERROR_BUS.lock().unwrap().read() {
+            self.errors.push(error);
+        }
+        self.draw_errors(ctx);
+        let now = SystemTime::now();
+        let since_autosave = now.duration_since(self.last_autosave).unwrap_or_default();
+        if self.recovered_hats.is_empty() && since_autosave >= AUTOSAVE_INTERVAL {
+            self.autosave();
+            self.last_autosave = now;
+        }
         self.last_time = SystemTime::now();
     }
 }
@@ -728,7 +1398,7 @@ fn main() -> color_eyre::eyre::Result<()> {
         ..Default::default()
     };
     let _ = eframe::run_native(
-        "Editor",
+        APP_NAME,
         native_opts,
         Box::new(|cc| {
             egui_extras::install_image_loaders(&cc.egui_ctx);
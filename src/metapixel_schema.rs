@@ -0,0 +1,525 @@
+//! Which metapixels every element type understands, and how they map onto its fields.
+//!
+//! Loading and saving both go through [`METAPIXEL_FIELDS`], so a new metapixel
+//! only needs a row there. Animations are blocks of several metapixels and are
+//! read with [`get_animation`] instead.
+
+use std::cell::RefCell;
+
+use bevy_math::IVec2;
+use num_traits::FromPrimitive;
+
+use crate::animations::AnimType;
+use crate::hat_utils::get_animation;
+use crate::hats::{
+    is_big_frame, AbstractHat, FlyingPet, PetBase, WalkingPet, DEFAULT_AUTO_SPEED,
+    DEFAULT_PET_DISTANCE, DEFAULT_PET_SPEED, DEFAULT_WINGS_IDLE_FRAME,
+};
+use crate::metapixels::Metapixels;
+use crate::prelude::*;
+
+/// Wing offsets are stored with this added, so 128 is no offset. Elements keep
+/// the stored value.
+pub const OFFSET_BIAS: i32 = 128;
+
+const PETS: &[HatType] = &[HatType::FlyingPet, HatType::WalkingPet];
+const ANIMATED: &[HatType] = &[
+    HatType::Wereable,
+    HatType::Wings,
+    HatType::Extra,
+    HatType::FlyingPet,
+    HatType::WalkingPet,
+];
+const BIG: &[HatType] = &[
+    HatType::Wereable,
+    HatType::Wings,
+    HatType::FlyingPet,
+    HatType::WalkingPet,
+];
+const LINKED: &[HatType] = &[HatType::Wereable, HatType::FlyingPet, HatType::WalkingPet];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MetapixelValue {
+    Flag(bool),
+    Number(i32),
+    Pair(IVec2),
+}
+
+impl MetapixelValue {
+    pub fn flag(self) -> bool {
+        matches!(self, MetapixelValue::Flag(true))
+    }
+    pub fn number(self) -> i32 {
+        match self {
+            MetapixelValue::Number(number) => number,
+            _ => 0,
+        }
+    }
+    pub fn pair(self) -> IVec2 {
+        match self {
+            MetapixelValue::Pair(pair) => pair,
+            _ => IVec2::ZERO,
+        }
+    }
+}
+
+/// How a value sits in the green and blue of its metapixel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    /// Set when the metapixel is there, its colors mean nothing.
+    Flag,
+    /// A number in green.
+    Number,
+    /// A frame in green, stored one less than the editor counts it.
+    FrameNumber,
+    /// Two numbers in green and blue.
+    Pair,
+    /// Like [`Encoding::Pair`], biased by [`OFFSET_BIAS`].
+    Offset,
+}
+
+impl Encoding {
+    pub fn decode(self, pixel: Metapixel) -> MetapixelValue {
+        let (g, b) = (pixel.g as i32, pixel.b as i32);
+        match self {
+            Encoding::Flag => MetapixelValue::Flag(true),
+            Encoding::Number => MetapixelValue::Number(g),
+            Encoding::FrameNumber => MetapixelValue::Number(pixel.g.saturating_add(1) as i32),
+            Encoding::Pair | Encoding::Offset => MetapixelValue::Pair(IVec2::new(g, b)),
+        }
+    }
+
+    /// Green and blue of the metapixel, `None` if the value is written by leaving it out.
+    pub fn encode(self, value: MetapixelValue) -> Option<(u8, u8)> {
+        match self {
+            Encoding::Flag => value.flag().then_some((0, 0)),
+            Encoding::Number => Some((value.number() as u8, 0)),
+            Encoding::FrameNumber => Some(((value.number() as u8).saturating_sub(1), 0)),
+            Encoding::Pair | Encoding::Offset => {
+                let pair = value.pair();
+                Some((pair.x as u8, pair.y as u8))
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FieldDefault {
+    /// Written whenever the element has a value, left alone when the metapixel is missing.
+    None,
+    /// Given when the metapixel is missing, and not written while the field has it.
+    Value(MetapixelValue),
+    /// Like [`FieldDefault::Value`], with the amount of frames of the element.
+    FramesAmount,
+}
+
+impl FieldDefault {
+    fn value(self, element: &dyn AbstractHat) -> Option<MetapixelValue> {
+        match self {
+            FieldDefault::None => None,
+            FieldDefault::Value(value) => Some(value),
+            FieldDefault::FramesAmount => {
+                Some(MetapixelValue::Number(element.frames_amount() as i32))
+            }
+        }
+    }
+}
+
+/// A metapixel with the field it sets. `get` gives `None` when there is nothing to write.
+pub struct MetapixelField {
+    pub pixel_type: MetapixelType,
+    pub hat_types: &'static [HatType],
+    pub encoding: Encoding,
+    pub default: FieldDefault,
+    pub get: fn(&dyn AbstractHat) -> Option<MetapixelValue>,
+    pub set: fn(&mut dyn AbstractHat, MetapixelValue),
+}
+
+/// A row for a field of one element type, kept the way the metapixel reads.
+macro_rules! field {
+    (
+        $pixel_type:ident,
+        $hat:ident.$field:ident: $value:ident($read:ident),
+        $encoding:ident,
+        $default:expr
+    ) => {
+        MetapixelField {
+            pixel_type: MetapixelType::$pixel_type,
+            hat_types: &[HatType::$hat],
+            encoding: Encoding::$encoding,
+            default: $default,
+            get: |e| Some(MetapixelValue::$value(e.downcast_ref::<$hat>()?.$field)),
+            set: |e, v| {
+                if let Some(hat) = e.downcast_mut::<$hat>() {
+                    hat.$field = v.$read()
+                }
+            },
+        }
+    };
+}
+
+const NO_FLAG: FieldDefault = FieldDefault::Value(MetapixelValue::Flag(false));
+const NO_OFFSET: FieldDefault =
+    FieldDefault::Value(MetapixelValue::Pair(IVec2::new(OFFSET_BIAS, OFFSET_BIAS)));
+
+/// Every metapixel an element field is saved as, in the order they are written.
+pub static METAPIXEL_FIELDS: &[MetapixelField] = &[
+    field!(StrappedOn, Wereable.strapped_on: Flag(flag), Flag, NO_FLAG),
+    MetapixelField {
+        pixel_type: MetapixelType::PetDistance,
+        hat_types: PETS,
+        encoding: Encoding::Number,
+        default: FieldDefault::Value(MetapixelValue::Number(DEFAULT_PET_DISTANCE)),
+        get: |e| Some(MetapixelValue::Number(pet_base(e)?.distance)),
+        set: |e, v| {
+            if let Some(pet) = pet_base_mut(e) {
+                pet.distance = v.number()
+            }
+        },
+    },
+    MetapixelField {
+        pixel_type: MetapixelType::PetNoFlip,
+        hat_types: PETS,
+        encoding: Encoding::Flag,
+        default: NO_FLAG,
+        get: |e| Some(MetapixelValue::Flag(!pet_base(e)?.flipped)),
+        set: |e, v| {
+            if let Some(pet) = pet_base_mut(e) {
+                pet.flipped = !v.flag()
+            }
+        },
+    },
+    field!(WingsGeneralOffset, Wings.general_offset: Pair(pair), Offset, NO_OFFSET),
+    field!(WingsSlideOffset, Wings.slide_offset: Pair(pair), Offset, NO_OFFSET),
+    field!(WingsRagdollOffset, Wings.ragdoll_offset: Pair(pair), Offset, NO_OFFSET),
+    field!(WingsCrouchOffset, Wings.crouch_offset: Pair(pair), Offset, NO_OFFSET),
+    field!(WingsNetOffset, Wings.net_offset: Pair(pair), Offset, NO_OFFSET),
+    // frames bigger than 32 pixels make the element big whether it says so or not
+    MetapixelField {
+        pixel_type: MetapixelType::IsBigHat,
+        hat_types: BIG,
+        encoding: Encoding::Flag,
+        default: NO_FLAG,
+        get: |e| {
+            let big = is_big(e)? || is_big_frame(e.base().frame_size);
+            Some(MetapixelValue::Flag(big))
+        },
+        set: |e, v| {
+            if let Some(is_big) = is_big_mut(e) {
+                *is_big = v.flag()
+            }
+        },
+    },
+    field!(GenerateWingsAnimations, Wings.gen_animations: Flag(flag), Flag, NO_FLAG),
+    field!(ChangeAnimationsEveryLevel, Wings.changes_animations: Flag(flag), Flag, NO_FLAG),
+    field!(
+        WingsAutoAnimationsSpeed,
+        Wings.auto_anim_speed: Number(number),
+        Number,
+        FieldDefault::Value(MetapixelValue::Number(DEFAULT_AUTO_SPEED))
+    ),
+    field!(
+        WingsAutoGlideFrame,
+        Wings.auto_glide_frame: Number(number),
+        FrameNumber,
+        FieldDefault::FramesAmount
+    ),
+    field!(
+        WingsAutoIdleFrame,
+        Wings.auto_idle_frame: Number(number),
+        FrameNumber,
+        FieldDefault::Value(MetapixelValue::Number(DEFAULT_WINGS_IDLE_FRAME))
+    ),
+    MetapixelField {
+        pixel_type: MetapixelType::FrameSize,
+        hat_types: ANIMATED,
+        encoding: Encoding::Pair,
+        default: FieldDefault::None,
+        get: |e| Some(MetapixelValue::Pair(e.base().frame_size)),
+        set: |e, v| e.base_mut().frame_size = v.pair(),
+    },
+    MetapixelField {
+        pixel_type: MetapixelType::OnSpawnAnimation,
+        hat_types: &[HatType::Wereable],
+        encoding: Encoding::Number,
+        default: FieldDefault::None,
+        get: |e| {
+            let anim_type = e.downcast_ref::<Wereable>()?.on_spawn_animation?;
+            Some(MetapixelValue::Number(anim_type as i32))
+        },
+        set: |e, v| {
+            if let Some(hat) = e.downcast_mut::<Wereable>() {
+                hat.on_spawn_animation = AnimType::from_i32(v.number())
+            }
+        },
+    },
+    MetapixelField {
+        pixel_type: MetapixelType::LinkFrameState,
+        hat_types: LINKED,
+        encoding: Encoding::Number,
+        default: FieldDefault::Value(MetapixelValue::Number(LinkFrameState::Default as i32)),
+        get: |e| {
+            let state = match e.downcast_ref::<Wereable>() {
+                Some(hat) => hat.link_frame_state,
+                None => pet_base(e)?.link_frame_state,
+            };
+            Some(MetapixelValue::Number(state as i32))
+        },
+        set: |e, v| {
+            let state = LinkFrameState::from(v.number() as u8);
+            if let Some(hat) = e.downcast_mut::<Wereable>() {
+                hat.link_frame_state = state
+            } else if let Some(pet) = pet_base_mut(e) {
+                pet.link_frame_state = state
+            }
+        },
+    },
+    field!(PetChangesAngle, FlyingPet.changes_angle: Flag(flag), Flag, NO_FLAG),
+    field!(
+        PetSpeed,
+        FlyingPet.speed: Number(number),
+        Number,
+        FieldDefault::Value(MetapixelValue::Number(DEFAULT_PET_SPEED))
+    ),
+];
+
+fn pet_base(element: &dyn AbstractHat) -> Option<&PetBase> {
+    match element.downcast_ref::<FlyingPet>() {
+        Some(pet) => Some(&pet.pet_base),
+        None => element
+            .downcast_ref::<WalkingPet>()
+            .map(|pet| &pet.pet_base),
+    }
+}
+
+fn pet_base_mut(element: &mut dyn AbstractHat) -> Option<&mut PetBase> {
+    if element.is::<FlyingPet>() {
+        return element
+            .downcast_mut::<FlyingPet>()
+            .map(|pet| &mut pet.pet_base);
+    }
+    element
+        .downcast_mut::<WalkingPet>()
+        .map(|pet| &mut pet.pet_base)
+}
+
+/// The flag IsBigHat sets, wings call it their size state.
+fn is_big(element: &dyn AbstractHat) -> Option<bool> {
+    if let Some(wings) = element.downcast_ref::<Wings>() {
+        return Some(wings.size_state);
+    }
+    match element.downcast_ref::<Wereable>() {
+        Some(hat) => Some(hat.is_big),
+        None => pet_base(element).map(|pet| pet.is_big),
+    }
+}
+
+fn is_big_mut(element: &mut dyn AbstractHat) -> Option<&mut bool> {
+    if element.is::<Wings>() {
+        return element
+            .downcast_mut::<Wings>()
+            .map(|wings| &mut wings.size_state);
+    }
+    if element.is::<Wereable>() {
+        return element
+            .downcast_mut::<Wereable>()
+            .map(|hat| &mut hat.is_big);
+    }
+    pet_base_mut(element).map(|pet| &mut pet.is_big)
+}
+
+/// Rows of the metapixels `hat_type` understands.
+pub fn fields_of(hat_type: HatType) -> impl Iterator<Item = &'static MetapixelField> {
+    METAPIXEL_FIELDS
+        .iter()
+        .filter(move |field| field.hat_types.contains(&hat_type))
+}
+
+/// Sets the element fields and animations from its metapixels. Fields without a
+/// metapixel get their default, and metapixels the element does not understand
+/// are kept as unknown.
+pub fn read_metapixels(element: &mut dyn AbstractHat, metapixels: &[Metapixel]) {
+    let hat_type = element.base().hat_type;
+    let mut found = vec![];
+    // an animation that could not be read is kept whole, its data included
+    let mut in_unread_animation = false;
+    for (i, pixel) in metapixels.iter().enumerate() {
+        let Some(pixel_type) = pixel.get_type() else {
+            continue;
+        };
+        if let Some(field) = fields_of(hat_type).find(|f| f.pixel_type == pixel_type) {
+            (field.set)(element, field.encoding.decode(*pixel));
+            found.push(pixel_type);
+            in_unread_animation = false;
+            continue;
+        }
+        let keep = match (pixel_type, element.animations_mut()) {
+            (MetapixelType::AnimationType, Some(animations)) => {
+                let animation = get_animation(metapixels, i);
+                in_unread_animation = animation.is_none();
+                if let Some(anim) = animation {
+                    animations.push(RefCell::new(anim).into())
+                }
+                in_unread_animation
+            }
+            (
+                MetapixelType::AnimationDelay
+                | MetapixelType::AnimationLoop
+                | MetapixelType::AnimationFrame
+                | MetapixelType::AnimationFramePeriod,
+                Some(_),
+            ) => in_unread_animation,
+            _ => {
+                in_unread_animation = false;
+                true
+            }
+        };
+        if keep {
+            element.base_mut().unknown_metapixels.push(*pixel);
+        }
+    }
+    for field in fields_of(hat_type) {
+        if found.contains(&field.pixel_type) {
+            continue;
+        }
+        if let Some(default) = field.default.value(element) {
+            (field.set)(element, default);
+        }
+    }
+    // settles fields that follow from others, like IsBigHat from the frame size
+    for field in fields_of(hat_type) {
+        if let Some(value) = (field.get)(element) {
+            (field.set)(element, value);
+        }
+    }
+}
+
+/// Metapixels the element fields are saved as, followed by its animations.
+pub fn write_metapixels(element: &dyn AbstractHat) -> Vec<Metapixel> {
+    let mut metapixels = Metapixels::new();
+    for field in fields_of(element.base().hat_type) {
+        let Some(value) = (field.get)(element) else {
+            continue;
+        };
+        if field.default.value(element) == Some(value) {
+            continue;
+        }
+        if let Some((g, b)) = field.encoding.encode(value) {
+            metapixels.push(field.pixel_type, g, b);
+        }
+    }
+    for anim in element.animations().into_iter().flatten() {
+        metapixels.push_many(&anim.borrow().gen_metapixels());
+    }
+    metapixels.pixels
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use bevy_math::IVec2;
+    use pixas::bitmap::Bitmap;
+
+    use crate::hats::{AbstractHat, HatBase, HatType, Wereable, Wings};
+    use crate::metapixel_schema::{read_metapixels, write_metapixels};
+    use crate::metapixels::{Metapixel, MetapixelType};
+
+    fn wings() -> Wings {
+        Wings {
+            base: HatBase {
+                hat_type: HatType::Wings,
+                frame_size: IVec2::new(32, 32),
+                bitmap: Some(Rc::new(Bitmap::with_size(96, 32))),
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn reads_back_what_it_writes() {
+        let mut written = wings();
+        read_metapixels(&mut written, &[]);
+        assert_eq!(written.auto_glide_frame, 3);
+        written.crouch_offset = IVec2::new(130, 120);
+        written.auto_glide_frame = 2;
+        written.gen_animations = true;
+        let metapixels = write_metapixels(&written);
+
+        let mut read = wings();
+        read_metapixels(&mut read, &metapixels);
+        assert_eq!(read.crouch_offset.to_array(), [130, 120]);
+        assert_eq!(read.general_offset.to_array(), [128, 128]);
+        assert_eq!(read.auto_glide_frame, 2);
+        assert_eq!(read.auto_idle_frame, 0);
+        assert_eq!(read.auto_anim_speed, 4);
+        assert!(read.gen_animations && !read.size_state);
+        assert!(read.base.unknown_metapixels.is_empty());
+
+        // defaults are left out, the frame size is always written
+        written.crouch_offset = IVec2::new(128, 128);
+        written.auto_glide_frame = 3;
+        written.gen_animations = false;
+        let types = write_metapixels(&written)
+            .iter()
+            .map(|m| m.get_type())
+            .collect::<Vec<_>>();
+        assert_eq!(types, [Some(MetapixelType::FrameSize)]);
+    }
+
+    #[test]
+    fn keeps_metapixels_of_other_elements_as_unknown() {
+        let mut wereable = Wereable {
+            base: HatBase {
+                hat_type: HatType::Wereable,
+                frame_size: IVec2::new(64, 32),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let speed = Metapixel {
+            r: MetapixelType::PetSpeed as u8,
+            g: 3,
+            b: 0,
+        };
+        read_metapixels(&mut wereable, &[speed]);
+        assert_eq!(wereable.base().unknown_metapixels.len(), 1);
+        assert!(wereable.is_big);
+    }
+
+    #[test]
+    fn keeps_animations_it_can_not_read() {
+        let mut wereable = Wereable {
+            base: HatBase {
+                hat_type: HatType::Wereable,
+                frame_size: IVec2::new(32, 32),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let pixel = |pixel_type: MetapixelType, g| Metapixel {
+            r: pixel_type as u8,
+            g,
+            b: 0,
+        };
+        let metapixels = [
+            pixel(MetapixelType::AnimationType, 200),
+            pixel(MetapixelType::AnimationDelay, 4),
+            pixel(MetapixelType::AnimationLoop, 1),
+            pixel(MetapixelType::AnimationFrame, 0),
+            pixel(MetapixelType::AnimationFrame, 1),
+            pixel(MetapixelType::StrappedOn, 0),
+        ];
+        read_metapixels(&mut wereable, &metapixels);
+        assert!(wereable.animations.is_empty());
+        assert!(wereable.strapped_on);
+        let unknown = wereable.base.unknown_metapixels.iter();
+        let unknown = unknown.map(|m| (m.r, m.g)).collect::<Vec<_>>();
+        let expected = metapixels[..5]
+            .iter()
+            .map(|m| (m.r, m.g))
+            .collect::<Vec<_>>();
+        assert_eq!(unknown, expected);
+    }
+}
@@ -1,4 +1,5 @@
 use num_traits::FromPrimitive;
+use thiserror::Error;
 
 macro_rules! metapixels {
     ($(($pixel_type:expr, $g:expr, $b:expr )),+ $(,)? ) => {
@@ -18,7 +19,7 @@ macro_rules! metapixels {
 
 pub(crate) use metapixels;
 
-#[derive(Debug, Clone, Copy, FromPrimitive)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, FromPrimitive)]
 pub enum MetapixelType {
     StrappedOn,
     IsBigHat,
@@ -46,7 +47,7 @@ pub enum MetapixelType {
     OnSpawnAnimation,
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Hash)]
 pub struct Metapixel {
     pub r: u8,
     pub g: u8,
@@ -54,8 +55,8 @@ pub struct Metapixel {
 }
 
 impl Metapixel {
-    pub fn get_type(&self) -> MetapixelType {
-        MetapixelType::from_u8(self.r).unwrap()
+    pub fn get_type(&self) -> Option<MetapixelType> {
+        MetapixelType::from_u8(self.r)
     }
 }
 
@@ -68,6 +69,32 @@ impl Metapixel {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+pub enum MetapixelErrorReason {
+    #[error("unknown metapixel type")]
+    UnknownType,
+    #[error("unknown animation type")]
+    UnknownAnimationType,
+    #[error("animation block is truncated")]
+    TruncatedAnimation,
+    #[error("animation data before AnimationType")]
+    FrameBeforeAnimationType,
+}
+
+/// A metapixel that could not be decoded. `x` is counted from the start
+/// of the metapixel column, so the first column is 0.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+#[error("metapixel at ({x}, {y}) with color ({r}, {g}, {b}): {reason}")]
+pub struct MetapixelParseError {
+    pub x: i32,
+    pub y: i32,
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    pub reason: MetapixelErrorReason,
+}
+
+#[derive(Default)]
 pub struct Metapixels {
     pub pixels: Vec<Metapixel>,
 }
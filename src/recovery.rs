@@ -0,0 +1,313 @@
+//! Snapshots of hats with unsaved changes, so a crash does not take them along.
+//!
+//! Every snapshot is a regular hat folder plus a small info file that remembers
+//! where the hat came from. The editor rewrites all of them at once and clears
+//! them on a clean exit, so whatever is left on launch was never saved.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::animations::Animation;
+use crate::archive::load_hat;
+use crate::hats::{AbstractHat, Hat, HatType};
+
+const INFO_FILE: &str = "info.json";
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RecoveryInfo {
+    pub title: String,
+    /// The folder the hat was opened from or last saved to.
+    pub path: Option<PathBuf>,
+}
+
+#[derive(Debug)]
+pub struct RecoveredHat {
+    pub dir: PathBuf,
+    pub info: RecoveryInfo,
+}
+
+impl RecoveredHat {
+    pub fn load(&self) -> Result<Hat> {
+        let mut hat = Hat::load(&self.dir)?;
+        hat.path = self.info.path.clone();
+        // the files of the snapshot are not the ones saving would replace
+        hat.files = match &self.info.path {
+            Some(path) if path.is_dir() => Hat::load(path).map(|h| h.files).unwrap_or_default(),
+            _ => vec![],
+        };
+        Ok(hat)
+    }
+
+    /// What the snapshot changes compared with the hat on disk.
+    pub fn changes(&self) -> Result<Vec<String>> {
+        let recovered = Hat::load(&self.dir)?;
+        let on_disk = match &self.info.path {
+            Some(path) if path.exists() => load_hat(path)?.0,
+            _ => Hat::default(),
+        };
+        Ok(describe_changes(&on_disk, &recovered))
+    }
+}
+
+/// Replaces every snapshot in `dir` with `hats`. A hat that fails to encode is
+/// skipped, the others are still worth keeping, and the ones that failed are
+/// the error.
+pub fn write_snapshots<'a>(
+    dir: &Path,
+    hats: impl IntoIterator<Item = (RecoveryInfo, &'a Hat)>,
+) -> Result<()> {
+    let temp_dir = dir.with_extension("tmp");
+    if temp_dir.exists() {
+        std::fs::remove_dir_all(&temp_dir)?;
+    }
+    std::fs::create_dir_all(&temp_dir)?;
+    let mut failed = vec![];
+    for (i, (info, hat)) in hats.into_iter().enumerate() {
+        let slot = temp_dir.join(i.to_string());
+        if let Err(err) = hat.clone().save(&slot) {
+            std::fs::remove_dir_all(&slot).ok();
+            failed.push(format!("{}: {err:#}", info.title));
+            continue;
+        }
+        std::fs::write(slot.join(INFO_FILE), serde_json::to_string_pretty(&info)?)?;
+    }
+    clear_snapshots(dir)?;
+    std::fs::rename(&temp_dir, dir)?;
+    if !failed.is_empty() {
+        bail!("no snapshot of {}", failed.join(", "));
+    }
+    Ok(())
+}
+
+pub fn clear_snapshots(dir: &Path) -> Result<()> {
+    if dir.exists() {
+        std::fs::remove_dir_all(dir)?;
+    }
+    Ok(())
+}
+
+/// Snapshots left in `dir`, in the order they were written.
+pub fn read_snapshots(dir: &Path) -> Vec<RecoveredHat> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return vec![];
+    };
+    let mut snapshots = entries
+        .flatten()
+        .filter_map(|entry| {
+            let dir = entry.path();
+            let info = std::fs::read_to_string(dir.join(INFO_FILE)).ok()?;
+            let info = serde_json::from_str(&info).ok()?;
+            Some(RecoveredHat { dir, info })
+        })
+        .collect::<Vec<_>>();
+    snapshots.sort_by_key(|s| {
+        s.dir
+            .file_name()
+            .and_then(|n| n.to_str()?.parse::<u32>().ok())
+    });
+    snapshots
+}
+
+/// Lists element by element what differs between two versions of a hat.
+pub fn describe_changes(old: &Hat, new: &Hat) -> Vec<String> {
+    let mut changes = vec![];
+    for element in new.iter_all_elements() {
+        match find_counterpart(old, element) {
+            Some(old_element) => {
+                for change in element_changes((old, old_element), (new, element)) {
+                    changes.push(format!("{}: {change}", element_label(element)));
+                }
+            }
+            None => changes.push(format!("{} added", element_label(element))),
+        }
+    }
+    for element in old.iter_all_elements() {
+        if find_counterpart(new, element).is_none() {
+            changes.push(format!("{} removed", element_label(element)));
+        }
+    }
+    changes
+}
+
+/// Unique elements are matched by type, pets also by name.
+fn find_counterpart<'a>(hat: &'a Hat, element: &dyn AbstractHat) -> Option<&'a dyn AbstractHat> {
+    let base = element.base();
+    hat.iter_all_elements().find(|e| {
+        e.base().hat_type == base.hat_type && (!is_pet(base.hat_type) || e.base().name == base.name)
+    })
+}
+
+fn is_pet(hat_type: HatType) -> bool {
+    matches!(hat_type, HatType::WalkingPet | HatType::FlyingPet)
+}
+
+fn element_label(element: &dyn AbstractHat) -> String {
+    let base = element.base();
+    match &base.name {
+        Some(name) if is_pet(base.hat_type) => format!("{} {name}", base.hat_type),
+        _ => base.hat_type.to_string(),
+    }
+}
+
+fn element_changes(
+    (old_hat, old): (&Hat, &dyn AbstractHat),
+    (new_hat, new): (&Hat, &dyn AbstractHat),
+) -> Vec<String> {
+    let (old_base, new_base) = (old.base(), new.base());
+    let mut changes = vec![];
+    if old_base.frame_size != new_base.frame_size {
+        changes.push(format!(
+            "frame size {}x{} -> {}x{}",
+            old_base.frame_size.x,
+            old_base.frame_size.y,
+            new_base.frame_size.x,
+            new_base.frame_size.y
+        ));
+    }
+    if old_base.hat_area_size != new_base.hat_area_size {
+        changes.push(format!(
+            "area size {}x{} -> {}x{}",
+            old_base.hat_area_size.x,
+            old_base.hat_area_size.y,
+            new_base.hat_area_size.x,
+            new_base.hat_area_size.y
+        ));
+    }
+    if !same_area_pixels(old, new) {
+        changes.push("image changed".to_string());
+    }
+
+    let old_animations = old.animations().cloned().unwrap_or_default();
+    let new_animations = new.animations().cloned().unwrap_or_default();
+    for animation in &new_animations {
+        let animation = animation.borrow();
+        let old_animation = old_animations
+            .iter()
+            .find(|a| a.borrow().anim_type == animation.anim_type);
+        match old_animation {
+            None => changes.push(format!("animation {} added", animation.anim_type)),
+            Some(old_animation) => {
+                let old_animation = old_animation.borrow();
+                let frames = |a: &Animation| a.frames.iter().map(|f| f.value).collect::<Vec<_>>();
+                if old_animation.delay != animation.delay
+                    || old_animation.looping != animation.looping
+                    || frames(&old_animation) != frames(&animation)
+                {
+                    changes.push(format!("animation {} changed", animation.anim_type));
+                }
+            }
+        }
+    }
+    for animation in &old_animations {
+        let anim_type = animation.borrow().anim_type;
+        if !new_animations
+            .iter()
+            .any(|a| a.borrow().anim_type == anim_type)
+        {
+            changes.push(format!("animation {anim_type} removed"));
+        }
+    }
+
+    // everything else ends up in the metapixels, animations were covered above
+    let metapixels_key = |hat: &Hat, element: &dyn AbstractHat| {
+        format!("{:?}", hat.element_metapixels(element.id()))
+    };
+    if changes.is_empty() && metapixels_key(old_hat, old) != metapixels_key(new_hat, new) {
+        changes.push("settings changed".to_string());
+    }
+    changes
+}
+
+fn same_area_pixels(old: &dyn AbstractHat, new: &dyn AbstractHat) -> bool {
+    let (Some(old_bitmap), Some(new_bitmap)) = (&old.base().bitmap, &new.base().bitmap) else {
+        return old.base().bitmap.is_none() && new.base().bitmap.is_none();
+    };
+    let area = new.base().hat_area_size;
+    for y in 0..area.y {
+        for x in 0..area.x {
+            let (a, b) = (old_bitmap.get_pixel(x, y), new_bitmap.get_pixel(x, y));
+            let same = match (a, b) {
+                (Some(a), Some(b)) => (a.r, a.g, a.b, a.a) == (b.r, b.g, b.b, b.a),
+                (a, b) => a.is_none() && b.is_none(),
+            };
+            if !same {
+                return false;
+            }
+        }
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use pixas::bitmap::Bitmap;
+
+    use crate::animations::{AnimType, Animation};
+    use crate::hat_utils::HatNameAndSize;
+    use crate::hats::{Hat, HatBase, HatType, LoadHat, Wereable};
+    use crate::recovery::{read_snapshots, write_snapshots, RecoveryInfo};
+
+    #[test]
+    fn restores_snapshots_and_lists_changes() {
+        let dir = std::env::temp_dir().join("hpp_restores_snapshots");
+        std::fs::remove_dir_all(&dir).ok();
+        let (hat_dir, recovery_dir) = (dir.join("hat"), dir.join("recovery"));
+
+        let name_and_size = HatNameAndSize::new("hat".to_string(), None);
+        let wereable = Wereable::from_bitmap(Bitmap::with_size(32, 32), name_and_size).unwrap();
+        let mut hat = Hat::new(hat_dir.clone());
+        hat.add_element(wereable);
+        hat.save(&hat_dir).unwrap();
+
+        let animation = Animation::new(AnimType::OnPressQuack, 4, false, vec![0.into()]);
+        let wereable = hat.wereable_mut().unwrap();
+        wereable.animations.push(RefCell::new(animation).into());
+        let info = RecoveryInfo {
+            title: "hat".to_string(),
+            path: Some(hat_dir.clone()),
+        };
+        write_snapshots(&recovery_dir, [(info, &hat)]).unwrap();
+
+        let snapshots = read_snapshots(&recovery_dir);
+        assert_eq!(snapshots.len(), 1);
+        assert_eq!(snapshots[0].load().unwrap().path, Some(hat_dir));
+        assert_eq!(
+            snapshots[0].changes().unwrap(),
+            vec!["Wearable Hat: animation On Press Quack added".to_string()]
+        );
+        std::fs::remove_dir_all(dir).ok();
+    }
+
+    #[test]
+    fn reports_hats_that_could_not_be_snapshotted() {
+        let dir = std::env::temp_dir().join("hpp_reports_failed_snapshots");
+        std::fs::remove_dir_all(&dir).ok();
+
+        let name_and_size = HatNameAndSize::new("hat".to_string(), None);
+        let wereable = Wereable::from_bitmap(Bitmap::with_size(32, 32), name_and_size).unwrap();
+        let mut hat = Hat::default();
+        hat.add_element(wereable);
+        // without an image there is nothing to save
+        let mut broken = Hat::default();
+        broken.add_element(Wereable {
+            base: HatBase {
+                hat_type: HatType::Wereable,
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+        let info = |title: &str| RecoveryInfo {
+            title: title.to_string(),
+            path: None,
+        };
+
+        let result = write_snapshots(&dir, [(info("hat"), &hat), (info("broken"), &broken)]);
+        assert!(result.unwrap_err().to_string().contains("broken"));
+        assert_eq!(read_snapshots(&dir).len(), 1);
+        std::fs::remove_dir_all(dir).ok();
+    }
+}
@@ -4,6 +4,7 @@ use std::sync::Arc;
 use std::{collections::HashMap, path::Path, rc::Rc};
 
 use bevy_math::IVec2;
+use eframe::egui::epaint::ViewportInPixels;
 use eframe::egui::Ui;
 use eframe::glow::{
     self, HasContext, NativeBuffer, NativeFramebuffer, NativeVertexArray, PixelUnpackData,
@@ -73,6 +74,7 @@ struct RenderData {
 }
 
 pub const RENDERER_SCREEN_SIZE: IVec2 = IVec2::splat(200);
+const SCREEN_SCALE: f32 = 2.0;
 
 impl RenderData {
     fn new(gl: &Context) -> Self {
@@ -178,13 +180,12 @@ impl Renderer {
     }
 
     fn draw_ui(&mut self, gl: &Context, ui: &mut Ui) {
-        let render_data = render_data(gl);
-        let shader = render_data.shader;
-        let texture = render_data.screen_texture;
+        let frame_buffer = render_data(gl).frame_buffer;
+        let screen_size = IVec2::new(self.screen.width as i32, self.screen.height as i32);
         let (rect, _) = ui.allocate_exact_size(
             eframe::egui::Vec2::new(
-                self.screen.width as f32 * 2.0,
-                self.screen.height as f32 * 5.0,
+                screen_size.x as f32 * SCREEN_SCALE,
+                screen_size.y as f32 * SCREEN_SCALE,
             ),
             eframe::egui::Sense {
                 click: false,
@@ -192,23 +193,49 @@ impl Renderer {
                 focusable: false,
             },
         );
-        let inner = texture.clone().inner();
         let callback = eframe::egui::PaintCallback {
             rect,
-            callback: Arc::new(egui_glow::CallbackFn::new(move |_, painter| {
-                Renderer::draw_texture_gl(inner, painter.gl(), shader.clone())
+            callback: Arc::new(egui_glow::CallbackFn::new(move |info, painter| {
+                let viewport = info.viewport_in_pixels();
+                Renderer::draw_texture_gl(painter, frame_buffer, screen_size, viewport)
             })),
         };
         ui.painter().add(callback);
     }
 
-    fn draw_texture_gl(_screen: crate::texture::Inner, _gl: &Context, _shader: Shader) {}
+    /// Copies the screen texture from the renderer framebuffer into the area egui gave us.
+    fn draw_texture_gl(
+        painter: &egui_glow::Painter,
+        frame_buffer: NativeFramebuffer,
+        screen_size: IVec2,
+        viewport: ViewportInPixels,
+    ) {
+        let gl = painter.gl();
+        unsafe {
+            gl.bind_framebuffer(glow::READ_FRAMEBUFFER, Some(frame_buffer));
+            // the screen bitmap is uploaded top row first, so flip it while copying
+            gl.blit_framebuffer(
+                0,
+                0,
+                screen_size.x,
+                screen_size.y,
+                viewport.left_px,
+                viewport.from_bottom_px + viewport.height_px,
+                viewport.left_px + viewport.width_px,
+                viewport.from_bottom_px,
+                glow::COLOR_BUFFER_BIT,
+                glow::NEAREST,
+            );
+            gl.bind_framebuffer(glow::READ_FRAMEBUFFER, painter.intermediate_fbo());
+        }
+    }
 
     fn draw_to_texture(&mut self, gl: &Context) {
         let render_data = render_data(gl);
         let screen_texture = render_data.screen_texture;
         let pixels_data = self.screen.get_pixel_data();
         unsafe {
+            gl.bind_texture(glow::TEXTURE_2D, Some(screen_texture.native()));
             gl.tex_sub_image_2d(
                 glow::TEXTURE_2D,
                 0,
@@ -0,0 +1,144 @@
+//! The element image split into frames, numbered the way animations count them.
+
+use std::collections::HashSet;
+
+use bevy_math::IVec2;
+use eframe::egui::{Align2, Color32, FontId, Id, Painter, Pos2, Rect, Sense, Stroke, Ui, Vec2};
+
+use crate::animation_window::paint_sheet;
+use crate::shader::Shader;
+use crate::texture::Texture;
+
+const MAX_PICKER_SCALE: f32 = 4.0;
+const LABEL_SIZE: f32 = 10.0;
+
+/// Frames of a sheet laid out on screen. Frames go row by row over the whole
+/// image, like in the animation shader.
+#[derive(Debug, Clone, Copy)]
+pub struct SheetGrid {
+    pub rect: Rect,
+    pub frame_size: IVec2,
+    /// Frames per row and per column.
+    pub frames: IVec2,
+    pub scale: f32,
+}
+
+impl SheetGrid {
+    pub fn new(min: Pos2, texture_size: IVec2, frame_size: IVec2, scale: f32) -> Self {
+        let frame_size = frame_size.max(IVec2::ONE);
+        let size = Vec2::new(texture_size.x as f32, texture_size.y as f32) * scale;
+        Self {
+            rect: Rect::from_min_size(min, size),
+            frame_size,
+            frames: texture_size / frame_size,
+            scale,
+        }
+    }
+
+    pub fn frames_amount(&self) -> i32 {
+        self.frames.x * self.frames.y
+    }
+
+    pub fn frame_rect(&self, index: i32) -> Rect {
+        let position = IVec2::new(index % self.frames.x, index / self.frames.x) * self.frame_size;
+        let min = self.rect.min + Vec2::new(position.x as f32, position.y as f32) * self.scale;
+        let size = Vec2::new(self.frame_size.x as f32, self.frame_size.y as f32) * self.scale;
+        Rect::from_min_size(min, size)
+    }
+
+    pub fn frame_at(&self, pos: Pos2) -> Option<i32> {
+        (0..self.frames_amount()).find(|&i| self.frame_rect(i).contains(pos))
+    }
+
+    /// Frames that `area` touches, in order.
+    pub fn frames_in(&self, area: Rect) -> Vec<i32> {
+        (0..self.frames_amount())
+            .filter(|&i| self.frame_rect(i).intersects(area))
+            .collect()
+    }
+
+    /// Outlines every frame and puts its number in the corner, counting from one
+    /// like the frame lists do. `highlighted` frames are tinted.
+    pub fn paint_frames(&self, painter: &Painter, highlighted: &HashSet<i32>, tint: Color32) {
+        let stroke = Stroke::new(1.0, Color32::from_black_alpha(160));
+        for i in 0..self.frames_amount() {
+            let rect = self.frame_rect(i);
+            if highlighted.contains(&i) {
+                painter.rect_filled(rect, 0.0, tint);
+            }
+            painter.rect_stroke(rect, 0.0, stroke);
+            let label_pos = rect.min + Vec2::splat(2.0);
+            let font = FontId::proportional(LABEL_SIZE);
+            let text = (i + 1).to_string();
+            painter.text(
+                label_pos + Vec2::splat(1.0),
+                Align2::LEFT_TOP,
+                &text,
+                font.clone(),
+                Color32::BLACK,
+            );
+            painter.text(label_pos, Align2::LEFT_TOP, text, font, Color32::WHITE);
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct PickerState {
+    /// Last clicked frame, shift clicks add the frames after it.
+    anchor: Option<i32>,
+    drag_origin: Option<Pos2>,
+}
+
+/// Shows the sheet and returns the frames picked this frame, to be appended to an
+/// animation. A click picks one frame, a shift click the range from the last
+/// clicked frame, and dragging a box picks every frame it touches.
+pub fn frame_picker(
+    ui: &mut Ui,
+    id: Id,
+    texture: &Texture,
+    frame_size: IVec2,
+    shader: Shader,
+    time: f32,
+    used_frames: &HashSet<i32>,
+) -> Vec<i32> {
+    let texture_size = IVec2::new(texture.width(), texture.height());
+    let scale = (ui.available_width() / texture_size.x as f32).clamp(1.0, MAX_PICKER_SCALE);
+    let size = Vec2::new(texture_size.x as f32, texture_size.y as f32) * scale;
+    let (rect, response) = ui.allocate_exact_size(size, Sense::click_and_drag());
+    let grid = SheetGrid::new(rect.min, texture_size, frame_size, scale);
+    paint_sheet(ui.painter(), rect, texture, shader, time);
+    let painter = ui.painter_at(rect);
+    let tint = ui.visuals().selection.bg_fill.gamma_multiply(0.5);
+    grid.paint_frames(&painter, used_frames, tint);
+
+    let mut state: PickerState = ui.data(|d| d.get_temp(id)).unwrap_or_default();
+    let mut picked = vec![];
+    let pointer = response.interact_pointer_pos();
+    if response.dragged() {
+        let press_origin = ui.input(|i| i.pointer.press_origin());
+        let origin = *state
+            .drag_origin
+            .get_or_insert(press_origin.or(pointer).unwrap_or(rect.min));
+        if let Some(pointer) = pointer {
+            let area = Rect::from_two_pos(origin, pointer);
+            let stroke = ui.visuals().selection.stroke;
+            painter.rect(area, 0.0, tint.gamma_multiply(0.5), stroke);
+        }
+    } else if let Some(origin) = state.drag_origin.take() {
+        let end = ui.input(|i| i.pointer.interact_pos()).unwrap_or(origin);
+        picked = grid.frames_in(Rect::from_two_pos(origin, end));
+        state.anchor = picked.last().copied().or(state.anchor);
+    } else if response.clicked() {
+        if let Some(frame) = pointer.and_then(|p| grid.frame_at(p)) {
+            let shift = ui.input(|i| i.modifiers.shift);
+            picked = match state.anchor {
+                Some(anchor) if shift && anchor < frame => (anchor + 1..=frame).collect(),
+                Some(anchor) if shift && anchor > frame => (frame..anchor).rev().collect(),
+                _ => vec![frame],
+            };
+            state.anchor = Some(frame);
+        }
+    }
+    ui.data_mut(|d| d.insert_temp(id, state));
+    picked
+}
@@ -0,0 +1,117 @@
+//! A dock tab with the whole image of the selected element, sliced into frames
+//! the way the animation window sees it.
+
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::rc::{Rc, Weak};
+
+use bevy_math::IVec2;
+use eframe::egui::{Align2, FontId, Rect, Sense, Slider, Stroke, Ui, Vec2};
+
+use crate::animation_window::{paint_sheet, TEXTURES_SCALE_FACTOR};
+use crate::colors;
+use crate::shader::Shader;
+use crate::sheet_grid::SheetGrid;
+use crate::texture::Texture;
+
+const MIN_ZOOM: f32 = 0.5;
+const MAX_ZOOM: f32 = 32.0;
+
+/// What a hat tab shows to its sheet views, updated by the tab every frame.
+#[derive(Debug, Default)]
+pub struct SheetSource {
+    pub texture: Option<Texture>,
+    pub frame_size: IVec2,
+    /// Everything right of the area holds metapixels.
+    pub area_size: IVec2,
+    /// Frame the animation window is showing.
+    pub current_frame: Option<i32>,
+}
+
+#[derive(Debug)]
+pub struct SheetView {
+    source: Weak<RefCell<SheetSource>>,
+    zoom: f32,
+    pan: Vec2,
+}
+
+impl SheetView {
+    pub fn new(source: &Rc<RefCell<SheetSource>>) -> Self {
+        Self {
+            source: Rc::downgrade(source),
+            zoom: TEXTURES_SCALE_FACTOR,
+            pan: Vec2::ZERO,
+        }
+    }
+
+    pub fn ui(&mut self, ui: &mut Ui, shader: Shader, time: f32) {
+        let Some(source) = self.source.upgrade() else {
+            ui.label("The hat of this view was closed.");
+            return;
+        };
+        let source = source.borrow();
+        let Some(texture) = &source.texture else {
+            ui.label("The selected element has no image.");
+            return;
+        };
+        ui.horizontal(|ui| {
+            ui.label("Zoom");
+            ui.add(Slider::new(&mut self.zoom, MIN_ZOOM..=MAX_ZOOM).logarithmic(true));
+            if ui.button("Reset").clicked() {
+                self.zoom = TEXTURES_SCALE_FACTOR;
+                self.pan = Vec2::ZERO;
+            }
+        });
+        let (view_rect, response) = ui.allocate_exact_size(ui.available_size(), Sense::drag());
+        if response.dragged() {
+            self.pan += response.drag_delta();
+        }
+        // ctrl and scroll, or pinch, zooms around the pointer
+        if let Some(pointer) = response.hover_pos() {
+            let zoom_delta = ui.input(|i| i.zoom_delta());
+            if zoom_delta != 1.0 {
+                let zoom = (self.zoom * zoom_delta).clamp(MIN_ZOOM, MAX_ZOOM);
+                let from_origin = pointer - (view_rect.min + self.pan);
+                self.pan -= from_origin * (zoom / self.zoom - 1.0);
+                self.zoom = zoom;
+            }
+        }
+
+        let texture_size = IVec2::new(texture.width(), texture.height());
+        let grid = SheetGrid::new(
+            view_rect.min + self.pan,
+            texture_size,
+            source.frame_size,
+            self.zoom,
+        );
+        let painter = ui.painter_at(view_rect);
+        paint_sheet(&painter, grid.rect, texture, shader, time);
+
+        let metapixels_rect = Rect::from_min_max(
+            grid.rect.min + Vec2::new(source.area_size.x as f32 * self.zoom, 0.0),
+            grid.rect.max,
+        );
+        if source.area_size.x > 0 && metapixels_rect.width() > 0.0 {
+            painter.rect(
+                metapixels_rect,
+                0.0,
+                colors::METAPIXELS.gamma_multiply(0.25),
+                Stroke::new(1.0, colors::METAPIXELS),
+            );
+            painter.text(
+                metapixels_rect.center_bottom() + Vec2::new(0.0, 2.0),
+                Align2::CENTER_TOP,
+                "Metapixels",
+                FontId::proportional(12.0),
+                colors::METAPIXELS,
+            );
+        }
+
+        let playing = HashSet::from_iter(source.current_frame);
+        let tint = ui.visuals().selection.bg_fill.gamma_multiply(0.5);
+        grid.paint_frames(&painter, &playing, tint);
+        if let Some(frame) = source.current_frame.filter(|&f| f < grid.frames_amount()) {
+            painter.rect_stroke(grid.frame_rect(frame), 0.0, ui.visuals().selection.stroke);
+        }
+    }
+}
@@ -17,3 +17,14 @@ pub const SAVE_AS: KeyboardShortcut = KeyboardShortcut::new(
     },
     Key::S,
 );
+pub const UNDO: KeyboardShortcut = KeyboardShortcut::new(Modifiers::CTRL, Key::Z);
+pub const REDO: KeyboardShortcut = KeyboardShortcut::new(
+    Modifiers {
+        ctrl: true,
+        shift: true,
+        alt: false,
+        mac_cmd: false,
+        command: false,
+    },
+    Key::Z,
+);
@@ -11,9 +11,12 @@ use eframe::{
 };
 
 use crate::{
-    animations::AnimType, prelude::Animation, shader::Shader, texture::Texture, VERTEX_ARRAY
+    animations::{AnimType, DUCK_GAME_HERTZ},
+    prelude::Animation,
+    shader::Shader,
+    texture::Texture,
+    VERTEX_ARRAY,
 };
-const DUCK_GAME_HERTZ: f32 = 60.0;
 
 #[derive(Debug, Clone, Copy)]
 struct Uniforms {
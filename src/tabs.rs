@@ -1,20 +1,29 @@
+use std::collections::HashSet;
 use std::fmt::Display;
 use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
 use std::sync::Mutex;
 
 use std::cell::RefCell;
 
-use bevy_math::IVec2;
+use anyhow::{bail, Result};
+use bevy_math::{IVec2, Vec2 as BVec2};
 use eframe::egui::{
-    self, include_image, Button, CollapsingHeader, Color32, Grid, Image, Key, Layout, RichText,
-    ScrollArea, TextureFilter, TextureWrapMode, Vec2,
+    self, include_image, Button, CollapsingHeader, Color32, Grid, Image, Key, Layout, Rect,
+    RichText, ScrollArea, Sense, Stroke, TextureFilter, TextureWrapMode, Vec2,
 };
 use eframe::emath::Numeric;
 use eframe::glow::Context;
 use egui_dnd::DragDropItem;
+use pixas::bitmap::Bitmap;
 
+use crate::animation_export::{
+    self, ExportBackground, ExportFormat, ExportOptions, MAX_EXPORT_SCALE,
+};
 use crate::animations::{AnimType, Frame};
+use crate::aseprite;
+use crate::frame_import;
 use crate::shader::Shader;
 use crate::sprite_drawer::{AnimChangeBehaviour, SpriteDrawer};
 use crate::texture_reloader::TextureReloader;
@@ -29,22 +38,92 @@ use egui_dock::{DockArea, DockState, NodeIndex, SurfaceIndex, TabViewer};
 use num_traits::ToPrimitive;
 
 use crate::animation_window::{AnimationWindow, AnimationWindowFrameData, TEXTURES_SCALE_FACTOR};
+use crate::colors;
 use crate::egui_utils;
 use crate::event_bus::EventBus;
 use crate::frames_from_range::frames_from_range;
+use crate::hat_utils::{decode_metapixels, describe_metapixels};
 use crate::hats::{
     AbstractHat, Hat, HatElementId, HatType, LinkFrameState, LoadHat, DEFAULT_AUTO_SPEED,
     DEFAULT_PET_DISTANCE, DEFAULT_PET_SPEED,
 };
-use crate::hats::{Extra, FlyingPet, WalkingPet, Wereable, Wings};
-use crate::renderer::Renderer;
+use crate::hats::{Extra, FlyingPet, RoomHat, WalkingPet, Wereable, Wings};
+use crate::history::HatHistory;
+use crate::metapixels::{Metapixel, MetapixelErrorReason};
+use crate::renderer::{Renderer, ScreenUpdate, RENDERER_SCREEN_SIZE};
+use crate::sheet_grid;
+use crate::sheet_view::{SheetSource, SheetView};
+use crate::sprite::{Depth, Sprite};
+use crate::texture::Texture;
+use crate::validation::{has_errors, validate_hat, Severity};
 
 pub enum NewHatEvent {
     Opened(std::path::PathBuf),
     New,
 }
 
+/// Roughly the size of a standing duck, used for scale in the room preview.
+const DUCK_SIZE: BVec2 = BVec2::new(16.0, 24.0);
+const DUCK_COLOR: Color32 = Color32::from_rgb(255, 255, 255);
+const ROOM_BACKGROUND: Color32 = Color32::from_rgb(30, 30, 46);
+
 pub static HAT_EVENT_BUS: Mutex<EventBus<NewHatEvent>> = Mutex::new(EventBus::new());
+/// Failures inside the tabs, the app shows them in its error window.
+pub static ERROR_BUS: Mutex<EventBus<String>> = Mutex::new(EventBus::new());
+
+/// Decodes an element, from a png or an Aseprite file, and uploads its texture.
+pub fn load_element<T: LoadHat>(path: impl AsRef<Path>, gl: &Context) -> Result<T> {
+    let mut hat = match aseprite::is_aseprite(path.as_ref()) {
+        true => aseprite::load_element(path.as_ref())?,
+        false => T::load_from_path(path)?,
+    };
+    hat.base_mut().load_texture(gl)?;
+    Ok(hat)
+}
+
+/// Adds an element made from the image at `path` to the hat and returns its texture.
+/// With `from_frames`, or when the image is a GIF, the frames are packed into a sheet
+/// with an `anim_type` animation, which is written into the hat folder if there is one.
+pub fn add_element_from<T: LoadHat>(
+    hat: &mut Hat,
+    path: &Path,
+    from_frames: bool,
+    anim_type: AnimType,
+    gl: &Context,
+) -> Result<Texture> {
+    let from_frames = from_frames || frame_import::is_gif(path);
+    let mut element: Box<dyn AbstractHat> = match from_frames {
+        true => Box::new(frame_import::import_frames::<T>(path, anim_type)?),
+        false => Box::new(load_element::<T>(path, gl)?),
+    };
+    if matches!(
+        element.base().hat_type,
+        HatType::WalkingPet | HatType::FlyingPet
+    ) {
+        hat.name_new_pet(&mut *element);
+    }
+    // the hat only gets the element once it is written and has a texture
+    if from_frames {
+        let written = match hat.path.clone().filter(|p| p.is_dir()) {
+            Some(dir) => {
+                frame_import::write_sheet(&mut *element, &dir)?;
+                element.base().path.clone()
+            }
+            None => None,
+        };
+        if let Err(err) = element.base_mut().load_texture(gl) {
+            if let Some(sheet_path) = written {
+                std::fs::remove_file(sheet_path).ok();
+            }
+            return Err(err);
+        }
+    }
+    let Some(texture) = element.texture().cloned() else {
+        bail!("the element has no texture");
+    };
+    hat.add_boxed_element(element);
+    Ok(texture)
+}
 
 pub enum HomeUIResponce {
     NewHat(NewHatEvent),
@@ -243,6 +322,7 @@ pub enum TabType {
     Regular,
     Home,
     Help,
+    Sheet,
 }
 
 #[derive(Debug)]
@@ -254,6 +334,46 @@ pub struct TabInner {
     pub renderer: Option<Renderer>,
     pub animation_window: AnimationWindow,
     pub keep_metapixels: bool,
+    pub history: HatHistory,
+    /// The tab was asked to close while modified, the app asks what to do with it.
+    pub close_requested: bool,
+    /// A rebuild was asked for while modified, the app asks what to do with the changes first.
+    pub rebuild_requested: bool,
+    /// Shared with the sheet views opened from this tab.
+    pub sheet_source: Rc<RefCell<SheetSource>>,
+    pub sheet_view: Option<SheetView>,
+}
+
+impl TabInner {
+    pub fn undo(&mut self) {
+        self.history.undo(&mut self.hat);
+    }
+
+    pub fn redo(&mut self) {
+        self.history.redo(&mut self.hat);
+    }
+
+    /// As of the last commit, the tab commits its edits once the pointer is released.
+    pub fn is_modified(&self) -> bool {
+        self.history.is_modified()
+    }
+
+    /// Frees what the tab holds on to once it is closed.
+    pub fn release(&mut self, gl: &Context, latest_hats: &mut Vec<PathBuf>) {
+        if let Some(path) = &self.hat.path {
+            if !latest_hats.iter().any(|p| p == path) {
+                latest_hats.push(path.clone());
+            }
+        }
+        // history snapshots share textures with the hat and each other
+        let mut deleted = HashSet::new();
+        let hats = std::iter::once(&self.hat).chain(self.history.hats());
+        for texture in hats.flat_map(|h| h.iter_all_elements().filter_map(|e| e.texture())) {
+            if deleted.insert(texture.native().0) {
+                texture.delete(gl);
+            }
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -265,12 +385,17 @@ impl Tab {
     pub fn new(title: String, hat: Hat) -> Self {
         let inner = RefCell::new(TabInner {
             title,
+            history: HatHistory::new(&hat),
             hat,
             tab_type: TabType::Regular,
             selected_hat_id: None,
             renderer: None,
             animation_window: AnimationWindow::new(),
             keep_metapixels: true,
+            close_requested: false,
+            rebuild_requested: false,
+            sheet_source: Default::default(),
+            sheet_view: None,
         });
         Self { inner }
     }
@@ -284,6 +409,11 @@ impl Tab {
             renderer: None,
             animation_window: AnimationWindow::new(),
             keep_metapixels: true,
+            history: HatHistory::new(&Hat::default()),
+            close_requested: false,
+            rebuild_requested: false,
+            sheet_source: Default::default(),
+            sheet_view: None,
         });
         Self { inner }
     }
@@ -297,6 +427,30 @@ impl Tab {
             renderer: None,
             animation_window: AnimationWindow::new(),
             keep_metapixels: true,
+            history: HatHistory::new(&Hat::default()),
+            close_requested: false,
+            rebuild_requested: false,
+            sheet_source: Default::default(),
+            sheet_view: None,
+        });
+        Self { inner }
+    }
+
+    /// A view of the element selected in the tab that owns `source`.
+    pub fn new_sheet(title: String, source: &Rc<RefCell<SheetSource>>) -> Self {
+        let inner = RefCell::new(TabInner {
+            title,
+            hat: Hat::default(),
+            tab_type: TabType::Sheet,
+            selected_hat_id: None,
+            renderer: None,
+            animation_window: AnimationWindow::new(),
+            keep_metapixels: true,
+            history: HatHistory::new(&Hat::default()),
+            close_requested: false,
+            rebuild_requested: false,
+            sheet_source: Default::default(),
+            sheet_view: Some(SheetView::new(source)),
         });
         Self { inner }
     }
@@ -348,6 +502,80 @@ fn ivec2_ui<Num: Numeric>(
     });
 }
 
+/// Swaps the image of an element for the one at `path`, keeping its metapixels.
+/// The old texture stays alive, the undo history may still point at it.
+fn replace_image(
+    gl: &Context,
+    texture_reloader: &mut TextureReloader,
+    base: &mut HatBase,
+    path: &Path,
+) -> Option<()> {
+    let bitmap = Bitmap::from_path(path).ok()?;
+    let texture = Texture::from_bitmap(gl, &bitmap, Some(path.to_owned())).ok()?;
+    texture_reloader.add_texture(&texture);
+    base.texture = Some(texture);
+    base.bitmap = Some(Rc::new(bitmap));
+    Some(())
+}
+
+fn export_animation_with_dialog(
+    element: &dyn AbstractHat,
+    anim_type: AnimType,
+    options: &ExportOptions,
+) -> Option<()> {
+    let animation = element
+        .animations()?
+        .iter()
+        .find(|a| a.borrow().anim_type == anim_type)?
+        .clone();
+    let extension = options.format.extension();
+    let path = rfd::FileDialog::new()
+        .add_filter(extension, &[extension])
+        .set_file_name(format!("{anim_type:?}.{extension}"))
+        .save_file()?;
+    let result = animation_export::export_animation(element, &animation.borrow(), &path, options);
+    if let Err(err) = result {
+        let message = format!("Could not export {anim_type}: {err:#}");
+        ERROR_BUS.lock().unwrap().send(message);
+        return None;
+    }
+    Some(())
+}
+
+/// Puts the room area in the middle of the preview with a duck standing on its floor,
+/// so it is easy to tell if the room is too small.
+fn draw_room_preview(renderer: &mut Renderer, room: &HatBase) {
+    let screen_size = RENDERER_SCREEN_SIZE.as_vec2();
+    renderer.sprite(&Sprite {
+        size: screen_size,
+        depth: Depth(0.0),
+        color: ROOM_BACKGROUND,
+        ..Default::default()
+    });
+    let area_size = room.hat_area_size.as_vec2();
+    let room_position = ((screen_size - area_size) / 2.0).floor();
+    // the sprite only covers the area, the metapixel column right of it is left out
+    if let Some(bitmap) = room.bitmap.clone() {
+        renderer.sprite(&Sprite {
+            size: area_size,
+            depth: Depth(1.0),
+            position: room_position,
+            ..Sprite::new(bitmap)
+        });
+    }
+    let duck_position = BVec2::new(
+        room_position.x + ((area_size.x - DUCK_SIZE.x) / 2.0).floor(),
+        room_position.y + area_size.y - DUCK_SIZE.y,
+    );
+    renderer.sprite(&Sprite {
+        size: DUCK_SIZE,
+        depth: Depth(2.0),
+        color: DUCK_COLOR,
+        position: duck_position,
+        ..Default::default()
+    });
+}
+
 impl MyTabViewer<'_> {
     fn remove_element_ui(&mut self, ui: &mut Ui) -> bool {
         egui_utils::red_button(
@@ -387,23 +615,39 @@ impl MyTabViewer<'_> {
                     hats::MIN_FRAME_SIZE..=hats::MAX_EXTRA_HAT_SIZE.y,
                     "Frame Size",
                 );
+                let anim_changes = self.draw_animations_ui(extra as &mut dyn AbstractHat, ui);
+                if let Some(anim) = anim_changes.added {
+                    if !extra
+                        .animations
+                        .iter()
+                        .any(|h| h.borrow().anim_type == anim)
+                    {
+                        extra
+                            .animations
+                            .push(RefCell::new(Animation::new(anim, 3, false, vec![])).into());
+                    }
+                }
+                if let Some(anim) = anim_changes.removed {
+                    extra.animations.retain(|a| a.borrow().anim_type != anim);
+                }
             });
         let _: Option<()> = try {
             let path = path?;
             if !inner.keep_metapixels {
-                let new_hat = Extra::load_from_path(path, self.frame_data.gl).ok()?;
+                let new_hat = load_element::<Extra>(path, self.frame_data.gl).ok()?;
                 inner.selected_hat_id = Some(new_hat.base().id);
                 self.frame_data
                     .texture_reloader
                     .add_texture(&new_hat.texture().unwrap().clone());
                 hat.replace_element(id, new_hat);
             } else {
-                let texture = extra.texture_mut()?;
-                let old_program = texture.native();
-                texture.replace_from_path(self.frame_data.gl, path);
-                self.frame_data
-                    .texture_reloader
-                    .update_texture_program(old_program, texture.native());
+                replace_image(
+                    self.frame_data.gl,
+                    self.frame_data.texture_reloader,
+                    extra.base_mut(),
+                    &path,
+                )?;
+                inner.history.mark_changed();
             }
         };
         if remove {
@@ -432,24 +676,15 @@ impl MyTabViewer<'_> {
                 remove = self.remove_element_ui(ui);
                 ui.horizontal(|ui| {
                     ui.label("Delay");
-                    let mut anim = wings.animations[0].borrow_mut();
-                    if ui
-                        .add(DragValue::new(&mut anim.delay).clamp_range(1..=255))
-                        .changed()
-                    {
-                        wings.auto_anim_speed = anim.delay;
-                    }
+                    ui.add(DragValue::new(&mut wings.auto_anim_speed).clamp_range(1..=255));
                     let plus = Button::new("+").min_size(Vec2::splat(18.0));
                     let minus = Button::new("-").min_size(Vec2::splat(18.0));
                     if ui.add(minus).clicked() {
-                        anim.delay -= 1;
-                        wings.auto_anim_speed = anim.delay;
+                        wings.auto_anim_speed -= 1;
                     } else if ui.add(plus).clicked() {
-                        anim.delay += 1;
-                        wings.auto_anim_speed = anim.delay;
+                        wings.auto_anim_speed += 1;
                     }
                     if ui.button("Reset").clicked() {
-                        anim.delay = DEFAULT_AUTO_SPEED;
                         wings.auto_anim_speed = DEFAULT_AUTO_SPEED;
                     }
                 });
@@ -506,20 +741,36 @@ impl MyTabViewer<'_> {
                     0..=255,
                     "Global offset",
                 );
+                let anim_changes = self.draw_animations_ui(wings as &mut dyn AbstractHat, ui);
+                if let Some(anim) = anim_changes.added {
+                    if !wings
+                        .animations
+                        .iter()
+                        .any(|h| h.borrow().anim_type == anim)
+                    {
+                        wings
+                            .animations
+                            .push(RefCell::new(Animation::new(anim, 3, false, vec![])).into());
+                    }
+                }
+                if let Some(anim) = anim_changes.removed {
+                    wings.animations.retain(|a| a.borrow().anim_type != anim);
+                }
             });
         let _: Option<()> = try {
             let path = path?;
             if !inner.keep_metapixels {
-                let new_hat = Wings::load_from_path(path, self.frame_data.gl).ok()?;
+                let new_hat = load_element::<Wings>(path, self.frame_data.gl).ok()?;
                 inner.selected_hat_id = Some(new_hat.base().id);
                 hat.replace_element(id, new_hat);
             } else {
-                let texture = wings.texture_mut()?;
-                let old_program = texture.native();
-                texture.replace_from_path(self.frame_data.gl, path);
-                self.frame_data
-                    .texture_reloader
-                    .update_texture_program(old_program, texture.native());
+                replace_image(
+                    self.frame_data.gl,
+                    self.frame_data.texture_reloader,
+                    wings.base_mut(),
+                    &path,
+                )?;
+                inner.history.mark_changed();
             }
         };
         if remove {
@@ -539,7 +790,7 @@ impl MyTabViewer<'_> {
         };
         let _: Option<()> = try {
             let path = path?;
-            let new_hat = Preview::load_from_path(path, self.frame_data.gl).ok()?;
+            let new_hat = load_element::<Preview>(path, self.frame_data.gl).ok()?;
             inner.selected_hat_id = Some(new_hat.base().id);
             hat.replace_element(id, new_hat);
         };
@@ -548,6 +799,52 @@ impl MyTabViewer<'_> {
             inner.selected_hat_id = None;
         }
     }
+    fn draw_room_ui(&mut self, ui: &mut Ui, inner: &mut TabInner) {
+        let hat = &mut inner.hat;
+        let room = hat.room_mut().unwrap();
+        let id = room.id();
+        let mut path = None;
+        let mut remove = false;
+        ScrollArea::new([true, true])
+            .drag_to_scroll(false)
+            .show(ui, |ui| {
+                ui.allocate_space((ui.available_width(), 1.0).into());
+                ui.heading("Room");
+                if ui.button("Set texture").clicked() {
+                    path = rfd::FileDialog::new().pick_file();
+                }
+                remove = self.remove_element_ui(ui);
+                let image_size = room.base().image_size();
+                let base = room.base_mut();
+                ivec2_ui(
+                    ui,
+                    &mut base.hat_area_size,
+                    1..=image_size.x.max(1),
+                    1..=image_size.y.max(1),
+                    "Area Size",
+                );
+                base.frame_size = base.hat_area_size;
+
+                let renderer = inner.renderer.get_or_insert_with(|| {
+                    Renderer::new(RENDERER_SCREEN_SIZE, ScreenUpdate::Clear)
+                });
+                draw_room_preview(renderer, room.base());
+                renderer.draw(self.frame_data.gl, ui);
+            });
+        let _: Option<()> = try {
+            let path = path?;
+            let new_hat = load_element::<RoomHat>(path, self.frame_data.gl).ok()?;
+            inner.selected_hat_id = Some(new_hat.base().id);
+            self.frame_data
+                .texture_reloader
+                .add_texture(&new_hat.texture().unwrap().clone());
+            hat.replace_element(id, new_hat);
+        };
+        if remove {
+            hat.remove_element(id);
+            inner.selected_hat_id = None;
+        }
+    }
     fn draw_flying_pet_ui(&mut self, ui: &mut Ui, inner: &mut TabInner, id: HatElementId) {
         let hat = &mut inner.hat;
         let flying_pet: &mut FlyingPet =
@@ -614,16 +911,17 @@ impl MyTabViewer<'_> {
         let _: Option<()> = try {
             let path = path?;
             if !inner.keep_metapixels {
-                let new_hat = FlyingPet::load_from_path(path, self.frame_data.gl).ok()?;
+                let new_hat = load_element::<FlyingPet>(path, self.frame_data.gl).ok()?;
                 inner.selected_hat_id = Some(new_hat.base().id);
                 hat.replace_element(id, new_hat);
             } else {
-                let texture = flying_pet.texture_mut()?;
-                let old_program = texture.native();
-                texture.replace_from_path(self.frame_data.gl, path);
-                self.frame_data
-                    .texture_reloader
-                    .update_texture_program(old_program, texture.native());
+                replace_image(
+                    self.frame_data.gl,
+                    self.frame_data.texture_reloader,
+                    flying_pet.base_mut(),
+                    &path,
+                )?;
+                inner.history.mark_changed();
             }
         };
         if remove {
@@ -689,16 +987,17 @@ impl MyTabViewer<'_> {
         let _: Option<()> = try {
             let path = path?;
             if !inner.keep_metapixels {
-                let new_hat = WalkingPet::load_from_path(path, self.frame_data.gl).ok()?;
+                let new_hat = load_element::<WalkingPet>(path, self.frame_data.gl).ok()?;
                 inner.selected_hat_id = Some(new_hat.base().id);
                 hat.replace_element(id, new_hat);
             } else {
-                let texture = walking_pet.texture_mut()?;
-                let old_program = texture.native();
-                texture.replace_from_path(self.frame_data.gl, path);
-                self.frame_data
-                    .texture_reloader
-                    .update_texture_program(old_program, texture.native());
+                replace_image(
+                    self.frame_data.gl,
+                    self.frame_data.texture_reloader,
+                    walking_pet.base_mut(),
+                    &path,
+                )?;
+                inner.history.mark_changed();
             }
         };
         if remove {
@@ -800,16 +1099,17 @@ impl MyTabViewer<'_> {
         let _: Option<()> = try {
             let path = path?;
             if !inner.keep_metapixels {
-                let new_hat = Wereable::load_from_path(path, self.frame_data.gl).ok()?;
+                let new_hat = load_element::<Wereable>(path, self.frame_data.gl).ok()?;
                 inner.selected_hat_id = Some(new_hat.base().id);
                 hat.replace_element(id, new_hat);
             } else {
-                let texture = wereable.texture_mut()?;
-                let old_program = texture.native();
-                texture.replace_from_path(self.frame_data.gl, path);
-                self.frame_data
-                    .texture_reloader
-                    .update_texture_program(old_program, texture.native());
+                replace_image(
+                    self.frame_data.gl,
+                    self.frame_data.texture_reloader,
+                    wereable.base_mut(),
+                    &path,
+                )?;
+                inner.history.mark_changed();
             }
         };
         if remove {
@@ -821,11 +1121,19 @@ impl MyTabViewer<'_> {
         let frames_amount = hat.frames_amount();
         let mut anim_to_delete = None;
         let mut anim_to_add = None;
+        let mut anim_to_export = None;
+        let mut export_all = false;
         let Some(avalible_anims) = animations::avalible_animations(hat.base().hat_type) else {
             return AnimationChanges::default();
         };
         let can_add_animations =
             avalible_anims.len() != hat.animations().map(|a| a.len()).unwrap_or(0);
+        let texture = hat.texture().cloned();
+        let frame_size = hat.base().frame_size;
+        let mut used_frames = HashSet::new();
+        for animation in hat.animations().into_iter().flatten() {
+            used_frames.extend(animation.borrow().frames.iter().map(|f| f.value));
+        }
         let open = match can_add_animations {
             true => None,
             false => Some(false),
@@ -921,9 +1229,28 @@ impl MyTabViewer<'_> {
                                 anim.frames = frames_from_range(range_start, range_end);
                             }
                         });
+                        if let Some(texture) = &texture {
+                            ui.collapsing("Pick frames", |ui| {
+                                ScrollArea::horizontal().show(ui, |ui| {
+                                    let picked = sheet_grid::frame_picker(
+                                        ui,
+                                        ui.id().with("frame_picker"),
+                                        texture,
+                                        frame_size,
+                                        self.frame_data.shader.clone(),
+                                        self.frame_data.time,
+                                        &used_frames,
+                                    );
+                                    anim.frames.extend(picked.into_iter().map(Frame::from));
+                                });
+                            });
+                        }
                         if ui.button("Clear Frames").clicked() {
                             anim.frames.clear();
                         }
+                        if ui.button("Export").clicked() {
+                            anim_to_export = Some(anim.anim_type);
+                        }
                         if egui_utils::red_button(
                             ui,
                             "Delete",
@@ -937,6 +1264,53 @@ impl MyTabViewer<'_> {
                 }
             }
         });
+        ui.collapsing("Export", |ui| {
+            let options = &mut self.frame_data.config.animation_export;
+            ui.horizontal(|ui| {
+                ui.label("Format");
+                ui.radio_value(&mut options.format, ExportFormat::Gif, "GIF");
+                ui.radio_value(&mut options.format, ExportFormat::Apng, "APNG");
+            });
+            ui.horizontal(|ui| {
+                ui.label("Scale");
+                ui.add(DragValue::new(&mut options.scale).clamp_range(1..=MAX_EXPORT_SCALE));
+            });
+            ui.horizontal(|ui| {
+                ui.label("Background");
+                ui.radio_value(
+                    &mut options.background,
+                    ExportBackground::Transparent,
+                    "None",
+                );
+                ui.radio_value(
+                    &mut options.background,
+                    ExportBackground::Checkerboard,
+                    "Checkerboard",
+                );
+                let solid = matches!(options.background, ExportBackground::Solid(_));
+                if ui.radio(solid, "Solid").clicked() && !solid {
+                    options.background = ExportBackground::Solid([255, 255, 255]);
+                }
+                if let ExportBackground::Solid(color) = &mut options.background {
+                    ui.color_edit_button_srgb(color);
+                }
+            });
+            if ui.button("Export all").clicked() {
+                export_all = true;
+            }
+        });
+        let options = self.frame_data.config.animation_export;
+        if let Some(anim_type) = anim_to_export {
+            export_animation_with_dialog(hat, anim_type, &options);
+        }
+        if export_all {
+            if let Some(dir) = rfd::FileDialog::new().pick_folder() {
+                if let Err(err) = animation_export::export_all_animations(hat, &dir, &options) {
+                    let message = format!("Could not export the animations: {err:#}");
+                    ERROR_BUS.lock().unwrap().send(message);
+                }
+            }
+        }
         AnimationChanges::new(anim_to_add.copied(), anim_to_delete)
     }
 
@@ -1120,6 +1494,188 @@ impl MyTabViewer<'_> {
         responce
     }
 
+    /// Lists what [`validate_hat`] found, clicking a finding selects its element.
+    fn draw_findings_ui(&mut self, ui: &mut Ui, inner: &mut TabInner) {
+        let findings = validate_hat(&inner.hat);
+        if findings.is_empty() {
+            return;
+        }
+        CollapsingHeader::new(format!("Problems ({})", findings.len()))
+            .default_open(has_errors(&findings))
+            .show(ui, |ui| {
+                if has_errors(&findings) {
+                    ui.label("The hat can not be saved until the errors are fixed.");
+                }
+                for finding in &findings {
+                    let color = match finding.severity {
+                        Severity::Error => ui.visuals().error_fg_color,
+                        Severity::Warning => ui.visuals().warn_fg_color,
+                    };
+                    let text = RichText::new(finding.to_string()).color(color);
+                    if ui.selectable_label(false, text).clicked() && finding.element.is_some() {
+                        inner.selected_hat_id = finding.element;
+                    }
+                }
+            });
+    }
+
+    /// Lists the metapixels of the selected element as they are in its image,
+    /// with what the editor makes of them.
+    fn draw_metapixels_ui(&mut self, ui: &mut Ui, inner: &TabInner) {
+        let Some(element) = inner
+            .selected_hat_id
+            .and_then(|id| inner.hat.element_from_id(id))
+        else {
+            return;
+        };
+        let base = element.base();
+        let Some(bitmap) = &base.bitmap else {
+            return;
+        };
+        CollapsingHeader::new("Metapixels").show(ui, |ui| {
+            let decoded = decode_metapixels(bitmap, base.hat_area_size);
+            let meanings = describe_metapixels(&decoded.metapixels);
+            let mut entries = decoded
+                .positions
+                .iter()
+                .zip(&decoded.metapixels)
+                .zip(meanings)
+                .map(|((&position, &pixel), meaning)| (position, pixel, meaning))
+                .collect::<Vec<_>>();
+            let unknown = decoded
+                .errors
+                .iter()
+                .filter(|e| e.reason == MetapixelErrorReason::UnknownType)
+                .map(|e| {
+                    let pixel = Metapixel {
+                        r: e.r,
+                        g: e.g,
+                        b: e.b,
+                    };
+                    (IVec2::new(e.x, e.y), pixel, format!("Unknown type {}", e.r))
+                });
+            entries.extend(unknown);
+            entries.sort_by_key(|(position, ..)| (position.x, position.y));
+            if entries.is_empty() {
+                ui.label("This element has no metapixels.");
+                return;
+            }
+
+            let id = ui.id().with(("selected_metapixel", base.id));
+            let mut selected: Option<IVec2> = ui.data(|d| d.get_temp(id)).flatten();
+            ScrollArea::vertical().max_height(200.0).show(ui, |ui| {
+                Grid::new("metapixels").striped(true).show(ui, |ui| {
+                    for header in ["Column", "Row", "Type", "G", "B", "Meaning"] {
+                        ui.strong(header);
+                    }
+                    ui.end_row();
+                    for (position, pixel, meaning) in &entries {
+                        let pixel_type = match pixel.get_type() {
+                            Some(pixel_type) => format!("{pixel_type:?}"),
+                            None => pixel.r.to_string(),
+                        };
+                        let column = base.hat_area_size.x + position.x;
+                        ui.label(column.to_string());
+                        ui.label(position.y.to_string());
+                        ui.label(pixel_type);
+                        ui.label(pixel.g.to_string());
+                        ui.label(pixel.b.to_string());
+                        let is_selected = selected == Some(*position);
+                        if ui.selectable_label(is_selected, meaning.as_str()).clicked() {
+                            selected = (!is_selected).then_some(*position);
+                        }
+                        ui.end_row();
+                    }
+                });
+            });
+            ui.data_mut(|d| d.insert_temp(id, selected));
+
+            let Some(texture) = element.texture() else {
+                return;
+            };
+            ScrollArea::horizontal().show(ui, |ui| {
+                let texture_size = Vec2::new(texture.width() as f32, texture.height() as f32);
+                let scale =
+                    (ui.available_width() / texture_size.x).clamp(1.0, TEXTURES_SCALE_FACTOR);
+                let size = texture_size * scale;
+                let (rect, _) = ui.allocate_exact_size(size, Sense::hover());
+                let shader = self.frame_data.shader.clone();
+                animation_window::paint_sheet(
+                    ui.painter(),
+                    rect,
+                    texture,
+                    shader,
+                    self.frame_data.time,
+                );
+                let Some(position) = selected else {
+                    return;
+                };
+                let pixel = IVec2::new(base.hat_area_size.x + position.x, position.y);
+                let pixel_rect = Rect::from_min_size(
+                    rect.min + Vec2::new(pixel.x as f32, pixel.y as f32) * scale,
+                    Vec2::splat(scale),
+                );
+                let stroke = Stroke::new(2.0, colors::METAPIXELS);
+                let painter = ui.painter();
+                painter.rect_stroke(pixel_rect.expand(1.0), 0.0, stroke);
+                painter.circle_stroke(pixel_rect.center(), scale.max(4.0) * 2.0, stroke);
+            });
+        });
+    }
+
+    /// Raw metapixels of the selected element, for the ones the editor has no settings for.
+    fn draw_advanced_ui(&mut self, ui: &mut Ui, inner: &mut TabInner) {
+        let Some(element) = inner
+            .selected_hat_id
+            .and_then(|id| inner.hat.element_from_id_mut(id))
+        else {
+            return;
+        };
+        let pixels = &mut element.base_mut().unknown_metapixels;
+        CollapsingHeader::new("Advanced").show(ui, |ui| {
+            ui.label("Raw metapixels, saved after the ones the editor writes itself.");
+            let mut swap = None;
+            let mut remove = None;
+            let len = pixels.len();
+            Grid::new("raw_metapixels").show(ui, |ui| {
+                for header in ["R", "G", "B", "Type", ""] {
+                    ui.strong(header);
+                }
+                ui.end_row();
+                for (i, pixel) in pixels.iter_mut().enumerate() {
+                    ui.add(DragValue::new(&mut pixel.r));
+                    ui.add(DragValue::new(&mut pixel.g));
+                    ui.add(DragValue::new(&mut pixel.b));
+                    match pixel.get_type() {
+                        Some(pixel_type) => ui.label(format!("{pixel_type:?}")),
+                        None => ui.label("Unknown"),
+                    };
+                    ui.horizontal(|ui| {
+                        if ui.add_enabled(i > 0, Button::new("⬆")).clicked() {
+                            swap = Some(i);
+                        }
+                        if ui.add_enabled(i + 1 < len, Button::new("⬇")).clicked() {
+                            swap = Some(i + 1);
+                        }
+                        if ui.button("X").clicked() {
+                            remove = Some(i);
+                        }
+                    });
+                    ui.end_row();
+                }
+            });
+            if let Some(i) = swap {
+                pixels.swap(i - 1, i);
+            }
+            if let Some(i) = remove {
+                pixels.remove(i);
+            }
+            if ui.button("Add metapixel").clicked() {
+                pixels.push(Metapixel { r: 0, g: 0, b: 0 });
+            }
+        });
+    }
+
     fn draw_hat_ui(&mut self, selected_hat_id: HatElementId, inner: &mut TabInner, ui: &mut Ui) {
         let hat_id = inner.selected_hat_id.unwrap();
         match inner.hat.hat_type_by_id(selected_hat_id).unwrap() {
@@ -1129,6 +1685,7 @@ impl MyTabViewer<'_> {
             HatType::WalkingPet => self.draw_walking_pet_ui(ui, inner, hat_id),
             HatType::Extra => self.draw_extra_hat_ui(ui, inner),
             HatType::Preview => self.draw_preview_ui(ui, inner),
+            HatType::Room => self.draw_room_ui(ui, inner),
             _ => {}
         };
     }
@@ -1138,7 +1695,12 @@ impl TabViewer for MyTabViewer<'_> {
     type Tab = Tab;
 
     fn title(&mut self, tab: &mut Self::Tab) -> egui::WidgetText {
-        tab.inner.borrow().title.as_str().into()
+        let inner = tab.inner.borrow();
+        if inner.history.is_modified() {
+            format!("{} *", inner.title).into()
+        } else {
+            inner.title.as_str().into()
+        }
     }
 
     fn ui(&mut self, ui: &mut Ui, tab: &mut Self::Tab) {
@@ -1157,8 +1719,25 @@ impl TabViewer for MyTabViewer<'_> {
                 self.help_ui(ui);
                 return;
             }
+            TabType::Sheet => {
+                if let Some(sheet_view) = &mut inner.sheet_view {
+                    let shader = self.frame_data.shader.clone();
+                    sheet_view.ui(ui, shader, self.frame_data.time);
+                }
+                return;
+            }
             TabType::Regular => {}
         };
+        // a drag is one edit, not one per frame
+        if !ui.input(|i| i.pointer.any_down()) {
+            inner.history.commit(&inner.hat);
+        }
+        // undo may have taken the selected element away
+        if let Some(id) = inner.selected_hat_id {
+            if inner.hat.element_from_id(id).is_none() {
+                inner.selected_hat_id = None;
+            }
+        }
         if inner.selected_hat_id.is_none() {
             if !inner.hat.has_elements() {
                 ui.label("Looks like this has is totaly empty! Maybe add an element or two?");
@@ -1186,11 +1765,24 @@ impl TabViewer for MyTabViewer<'_> {
                     .unwrap();
             }
         });
+        self.draw_findings_ui(ui, inner);
+        self.draw_metapixels_ui(ui, inner);
+        self.draw_advanced_ui(ui, inner);
         let selected_hat_id = inner.selected_hat_id.unwrap();
         let hat_name = inner.title.clone();
         let selected_hat = inner.hat.element_from_id_mut(selected_hat_id).unwrap();
         let frame_size = selected_hat.base().frame_size;
-        let animations = selected_hat.animations().map(|a| a.to_vec());
+        let mut animations = selected_hat.animations().map(|a| a.to_vec());
+        if let Some(wings) = selected_hat.downcast_ref::<Wings>() {
+            if wings.animations.is_empty() {
+                animations = Some(vec![RefCell::new(wings.auto_animation()).into()]);
+            }
+        }
+        if let Some(extra) = selected_hat.downcast_ref::<Extra>() {
+            if extra.animations.is_empty() {
+                animations = Some(vec![RefCell::new(extra.default_animation()).into()]);
+            }
+        }
         if let Some(texture) = selected_hat.texture().cloned() {
             //keep calm and call clone, right?
             inner.animation_window.draw(AnimationWindowFrameData {
@@ -1205,6 +1797,12 @@ impl TabViewer for MyTabViewer<'_> {
                 time: self.frame_data.time,
             });
         }
+        *inner.sheet_source.borrow_mut() = SheetSource {
+            texture: selected_hat.texture().cloned(),
+            frame_size,
+            area_size: selected_hat.base().hat_area_size,
+            current_frame: Some(inner.animation_window.current_frame),
+        };
         self.draw_hat_ui(selected_hat_id, inner, ui);
     }
 
@@ -1213,14 +1811,12 @@ impl TabViewer for MyTabViewer<'_> {
     }
 
     fn on_close(&mut self, tab: &mut Self::Tab) -> bool {
-        let hat = &mut tab.inner.borrow_mut().hat;
-        let latest_hats = &mut self.frame_data.config.latest_hats;
-        if let Some(path) = &hat.path {
-            if !latest_hats.iter().any(|p| p == path) {
-                latest_hats.push(path.clone());
-            }
+        let inner = &mut tab.inner.borrow_mut();
+        if inner.is_modified() {
+            inner.close_requested = true;
+            return false;
         }
-        hat.delete_textures(self.frame_data.gl);
+        inner.release(self.frame_data.gl, &mut self.frame_data.config.latest_hats);
         true
     }
 
@@ -61,6 +61,10 @@ impl Texture {
 
     pub fn from_path(gl: &Context, path: impl AsRef<Path>) -> Result<Self> {
         let bitmap = Bitmap::from_path(path.as_ref())?;
+        Texture::from_bitmap(gl, &bitmap, Some(path.as_ref().to_owned()))
+    }
+
+    pub fn from_bitmap(gl: &Context, bitmap: &Bitmap, path: Option<PathBuf>) -> Result<Self> {
         if bitmap.width == 0 || bitmap.height == 0 {
             bail!(
                 "tried to create empty texture with size {0}",
@@ -95,7 +99,7 @@ impl Texture {
                     height: bitmap.height as i32,
                     native: texture,
                 })),
-                path: Some(path.as_ref().to_owned()),
+                path,
             })
         }
     }
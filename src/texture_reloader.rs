@@ -21,7 +21,7 @@ impl TextureData {
         }
     }
 }
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub struct TextureReloader {
     textures: Vec<TextureData>,
 }
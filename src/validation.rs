@@ -0,0 +1,291 @@
+//! Checks a [`Hat`] for things the game would choke on or that would not
+//! survive saving. The editor shows the findings in each hat tab and refuses
+//! to save while any of them is an error.
+
+use std::fmt::Display;
+
+use bevy_math::IVec2;
+
+use crate::hat_utils::decode_metapixels;
+use crate::hats::{
+    AbstractHat, Hat, HatElementId, HatType, Wereable, Wings, MAX_EXTRA_HAT_SIZE, MAX_PETS,
+};
+use crate::metapixels::MetapixelErrorReason;
+
+/// Largest value a metapixel channel can hold.
+const MAX_CHANNEL_VALUE: i32 = u8::MAX as i32;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Severity::Warning => f.write_str("warning"),
+            Severity::Error => f.write_str("error"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Finding {
+    pub severity: Severity,
+    /// The element the finding is about, `None` for the hat as a whole.
+    pub element: Option<HatElementId>,
+    pub hat_type: Option<HatType>,
+    pub message: String,
+}
+
+impl Finding {
+    fn new(severity: Severity, element: &dyn AbstractHat, message: String) -> Self {
+        Self {
+            severity,
+            element: Some(element.id()),
+            hat_type: Some(element.base().hat_type),
+            message,
+        }
+    }
+}
+
+impl Display for Finding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.hat_type {
+            Some(hat_type) => write!(f, "{hat_type}: {}", self.message),
+            None => f.write_str(&self.message),
+        }
+    }
+}
+
+pub fn has_errors(findings: &[Finding]) -> bool {
+    findings.iter().any(|f| f.severity == Severity::Error)
+}
+
+/// Runs every check over the hat. Errors come first.
+pub fn validate_hat(hat: &Hat) -> Vec<Finding> {
+    let mut findings = vec![];
+    if hat.pets.len() > MAX_PETS {
+        findings.push(Finding {
+            severity: Severity::Error,
+            element: None,
+            hat_type: None,
+            message: format!(
+                "hat has {} pets, but at most {MAX_PETS} are loaded",
+                hat.pets.len()
+            ),
+        });
+    }
+    for element in hat.iter_all_elements() {
+        validate_element(element, &mut findings);
+    }
+    findings.sort_by_key(|f| std::cmp::Reverse(f.severity));
+    findings
+}
+
+fn validate_element(element: &dyn AbstractHat, findings: &mut Vec<Finding>) {
+    let base = element.base();
+    let (frame_size, area_size) = (base.frame_size, base.hat_area_size);
+    let mut push = |severity, message| findings.push(Finding::new(severity, element, message));
+
+    if frame_size.x <= 0 || frame_size.y <= 0 {
+        push(Severity::Error, "frame size is zero".to_string());
+        return;
+    }
+    if frame_size.x > MAX_CHANNEL_VALUE || frame_size.y > MAX_CHANNEL_VALUE {
+        push(
+            Severity::Error,
+            format!(
+                "frame size {}x{} does not fit into a metapixel",
+                frame_size.x, frame_size.y
+            ),
+        );
+    }
+    let has_frames = !matches!(base.hat_type, HatType::Preview | HatType::Room);
+    if has_frames && (area_size.x % frame_size.x != 0 || area_size.y % frame_size.y != 0) {
+        push(
+            Severity::Warning,
+            format!(
+                "area size {}x{} is not a multiple of frame size {}x{}",
+                area_size.x, area_size.y, frame_size.x, frame_size.y
+            ),
+        );
+    }
+    if base.hat_type == HatType::Extra
+        && (frame_size.x > MAX_EXTRA_HAT_SIZE.x || frame_size.y > MAX_EXTRA_HAT_SIZE.y)
+    {
+        push(
+            Severity::Error,
+            format!(
+                "frame size {}x{} is larger than the maximum of {}x{}",
+                frame_size.x, frame_size.y, MAX_EXTRA_HAT_SIZE.x, MAX_EXTRA_HAT_SIZE.y
+            ),
+        );
+    }
+
+    let frames_amount = element.frames_amount() as i32;
+    for animation in element.animations().into_iter().flatten() {
+        let animation = animation.borrow();
+        if animation.delay < 0 || animation.delay > MAX_CHANNEL_VALUE {
+            push(
+                Severity::Error,
+                format!(
+                    "animation {} has delay {}, which does not fit into a metapixel",
+                    animation.anim_type, animation.delay
+                ),
+            );
+        }
+        for frame in &animation.frames {
+            if frame.value < 0 || frame.value > MAX_CHANNEL_VALUE {
+                push(
+                    Severity::Error,
+                    format!(
+                        "animation {} uses frame {}, which does not fit into a metapixel",
+                        animation.anim_type, frame.value
+                    ),
+                );
+            } else if frame.value >= frames_amount {
+                push(
+                    Severity::Error,
+                    format!(
+                        "animation {} uses frame {}, but there are only {frames_amount}",
+                        animation.anim_type, frame.value
+                    ),
+                );
+            }
+        }
+    }
+
+    if let Some(wings) = element.downcast_ref::<Wings>() {
+        let offsets = [
+            ("general", wings.general_offset),
+            ("crouch", wings.crouch_offset),
+            ("ragdoll", wings.ragdoll_offset),
+            ("slide", wings.slide_offset),
+            ("net", wings.net_offset),
+        ];
+        let in_range = |v: i32| (0..=MAX_CHANNEL_VALUE).contains(&v);
+        for (name, offset) in offsets {
+            if !in_range(offset.x) || !in_range(offset.y) {
+                push(
+                    Severity::Error,
+                    format!(
+                        "{name} offset {}x{} is outside of 0..=255",
+                        offset.x, offset.y
+                    ),
+                );
+            }
+        }
+    }
+
+    let on_spawn_animation = element
+        .downcast_ref::<Wereable>()
+        .and_then(|w| w.on_spawn_animation);
+    if let Some(spawn_anim) = on_spawn_animation {
+        let mut animations = element.animations().into_iter().flatten();
+        if !animations.any(|a| a.borrow().anim_type == spawn_anim) {
+            push(
+                Severity::Warning,
+                format!("on spawn animation {spawn_anim} does not exist"),
+            );
+        }
+    }
+
+    if has_frames {
+        for position in art_in_metapixel_column(element) {
+            push(
+                Severity::Warning,
+                format!(
+                    "pixel at ({}, {}) is in the metapixel column, but is not a metapixel",
+                    position.x, position.y
+                ),
+            );
+        }
+    }
+}
+
+/// Image positions of pixels right of the hat area that do not decode as metapixels,
+/// usually art that spilled over the area.
+fn art_in_metapixel_column(element: &dyn AbstractHat) -> Vec<IVec2> {
+    let base = element.base();
+    let Some(bitmap) = &base.bitmap else {
+        return vec![];
+    };
+    decode_metapixels(bitmap, base.hat_area_size)
+        .errors
+        .iter()
+        .filter(|e| e.reason == MetapixelErrorReason::UnknownType)
+        .map(|e| IVec2::new(base.hat_area_size.x + e.x, e.y))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use bevy_math::IVec2;
+    use pixas::bitmap::Bitmap;
+
+    use crate::animations::{AnimType, Animation};
+    use crate::hats::{Hat, HatBase, HatType, Wereable, Wings};
+    use crate::validation::{has_errors, validate_hat, Severity};
+
+    fn wereable(frames: i32) -> Wereable {
+        Wereable {
+            base: HatBase {
+                hat_type: HatType::Wereable,
+                frame_size: IVec2::new(32, 32),
+                hat_area_size: IVec2::new(32 * frames, 32),
+                bitmap: Some(Rc::new(Bitmap::with_size(32 * frames as u32, 32))),
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn flags_frames_out_of_range_and_broken_offsets() {
+        let mut hat = Hat::default();
+        let mut wereable = wereable(2);
+        let animation = Animation::new(AnimType::OnDefault, 4, false, vec![1.into(), 2.into()]);
+        wereable.animations.push(RefCell::new(animation).into());
+        wereable.on_spawn_animation = Some(AnimType::OnDuckDeath);
+        hat.add_element(wereable);
+        hat.add_element(Wings {
+            net_offset: IVec2::new(300, 128),
+            base: HatBase {
+                hat_type: HatType::Wings,
+                frame_size: IVec2::new(32, 32),
+                hat_area_size: IVec2::new(32, 32),
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+
+        let findings = validate_hat(&hat);
+        let messages = findings
+            .iter()
+            .map(|f| (f.severity, f.message.as_str()))
+            .collect::<Vec<_>>();
+        assert!(messages.contains(&(
+            Severity::Error,
+            "animation On Default uses frame 2, but there are only 2"
+        )));
+        assert!(messages.contains(&(Severity::Error, "net offset 300x128 is outside of 0..=255")));
+        assert!(messages.contains(&(
+            Severity::Warning,
+            "on spawn animation On Duck Death does not exist"
+        )));
+        assert!(has_errors(&findings));
+        assert_eq!(findings[0].severity, Severity::Error);
+    }
+
+    #[test]
+    fn accepts_a_valid_hat() {
+        let mut hat = Hat::default();
+        hat.add_element(wereable(1));
+        assert!(validate_hat(&hat).is_empty());
+    }
+}
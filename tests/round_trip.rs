@@ -0,0 +1,194 @@
+//! Decodes every bundled sample hat, encodes it again and checks nothing changed.
+//!
+//! The decoded models are also compared against `tests/golden`, so format changes
+//! show up in review. Run with `HPP_BLESS=1` to rewrite the golden files.
+
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+use hats_plus_plus_editor::animations::Animation;
+use hats_plus_plus_editor::file_utils::FileStemString;
+use hats_plus_plus_editor::hat_utils::get_name_and_size;
+use hats_plus_plus_editor::hats::{
+    decode_element, AbstractHat, FlyingPet, Hat, HatType, PetBase, WalkingPet, Wereable, Wings,
+};
+
+const SAMPLE_ROOTS: [&str; 4] = ["Hats", "PalasHat", "Terraria29", "TerrariaHatsPack"];
+const GOLDEN_DIR: &str = "tests/golden";
+
+fn is_hat_dir(dir: &Path) -> bool {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return false;
+    };
+    entries.flatten().any(|entry| {
+        entry.path().is_file()
+            && entry
+                .path()
+                .file_stem_string()
+                .and_then(|stem| HatType::from_file_name(&get_name_and_size(&stem).name))
+                .is_some()
+    })
+}
+
+fn collect_hat_dirs(dir: &Path, hat_dirs: &mut Vec<PathBuf>) {
+    if is_hat_dir(dir) {
+        hat_dirs.push(dir.to_path_buf());
+    }
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        if entry.path().is_dir() {
+            collect_hat_dirs(&entry.path(), hat_dirs);
+        }
+    }
+}
+
+fn sample_hat_dirs() -> Vec<PathBuf> {
+    let mut hat_dirs = vec![];
+    for root in SAMPLE_ROOTS {
+        collect_hat_dirs(Path::new(root), &mut hat_dirs);
+    }
+    hat_dirs.sort();
+    hat_dirs
+}
+
+fn sorted_elements(hat: &Hat) -> Vec<&dyn AbstractHat> {
+    let mut elements = hat.iter_all_elements().collect::<Vec<_>>();
+    elements.sort_by_key(|e| (e.base().hat_type as i32, e.base().path.clone()));
+    elements
+}
+
+fn describe_animation(out: &mut String, animation: &Animation) {
+    let frames = animation.frames.iter().map(|f| f.value).collect::<Vec<_>>();
+    writeln!(
+        out,
+        "animation: {:?} delay {} looping {} frames {:?}",
+        animation.anim_type, animation.delay, animation.looping, frames
+    )
+    .unwrap();
+}
+
+fn describe_pet_base(out: &mut String, pet_base: &PetBase) {
+    writeln!(out, "distance: {}", pet_base.distance).unwrap();
+    writeln!(out, "flipped: {}", pet_base.flipped).unwrap();
+    writeln!(out, "big: {}", pet_base.is_big).unwrap();
+    writeln!(out, "link frame state: {:?}", pet_base.link_frame_state).unwrap();
+}
+
+/// Everything the editor models about an element, except where it came from.
+fn describe(element: &dyn AbstractHat) -> String {
+    let mut out = String::new();
+    let base = element.base();
+    writeln!(out, "type: {:?}", base.hat_type).unwrap();
+    writeln!(
+        out,
+        "frame size: {}x{}",
+        base.frame_size.x, base.frame_size.y
+    )
+    .unwrap();
+    let area = base.hat_area_size;
+    writeln!(out, "area size: {}x{}", area.x, area.y).unwrap();
+    writeln!(out, "frames: {}", element.frames_amount()).unwrap();
+
+    if let Some(wereable) = element.downcast_ref::<Wereable>() {
+        writeln!(out, "strapped on: {}", wereable.strapped_on).unwrap();
+        writeln!(out, "big: {}", wereable.is_big).unwrap();
+        writeln!(out, "link frame state: {:?}", wereable.link_frame_state).unwrap();
+        writeln!(out, "on spawn animation: {:?}", wereable.on_spawn_animation).unwrap();
+    }
+    if let Some(wings) = element.downcast_ref::<Wings>() {
+        let offsets = [
+            ("general", wings.general_offset),
+            ("crouch", wings.crouch_offset),
+            ("ragdoll", wings.ragdoll_offset),
+            ("slide", wings.slide_offset),
+            ("net", wings.net_offset),
+        ];
+        for (name, offset) in offsets {
+            writeln!(out, "{name} offset: {}x{}", offset.x, offset.y).unwrap();
+        }
+        writeln!(out, "generate animations: {}", wings.gen_animations).unwrap();
+        writeln!(out, "auto glide frame: {}", wings.auto_glide_frame).unwrap();
+        writeln!(out, "auto idle frame: {}", wings.auto_idle_frame).unwrap();
+        writeln!(out, "auto animations speed: {}", wings.auto_anim_speed).unwrap();
+        writeln!(out, "changes animations: {}", wings.changes_animations).unwrap();
+        writeln!(out, "big: {}", wings.size_state).unwrap();
+    }
+    if let Some(pet) = element.downcast_ref::<FlyingPet>() {
+        describe_pet_base(&mut out, &pet.pet_base);
+        writeln!(out, "changes angle: {}", pet.changes_angle).unwrap();
+        writeln!(out, "speed: {}", pet.speed).unwrap();
+    }
+    if let Some(pet) = element.downcast_ref::<WalkingPet>() {
+        describe_pet_base(&mut out, &pet.pet_base);
+    }
+
+    for animation in element.animations().into_iter().flatten() {
+        describe_animation(&mut out, &animation.borrow());
+    }
+    for pixel in &base.unknown_metapixels {
+        writeln!(
+            out,
+            "unknown metapixel: ({}, {}, {})",
+            pixel.r, pixel.g, pixel.b
+        )
+        .unwrap();
+    }
+    out
+}
+
+fn golden_path(hat_dir: &Path) -> PathBuf {
+    let name = hat_dir
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("__");
+    Path::new(GOLDEN_DIR).join(format!("{name}.txt"))
+}
+
+#[test]
+fn sample_hats_survive_encoding() {
+    for hat_dir in sample_hat_dirs() {
+        let hat = Hat::load(&hat_dir).unwrap();
+        for element in sorted_elements(&hat) {
+            let context = format!("{:?} in {}", element.base().path, hat_dir.display());
+            let (file_name, bitmap) = hat.encode_element(element.id()).unwrap();
+            let name_and_size = get_name_and_size(&file_name.file_stem_string().unwrap());
+            let decoded = decode_element(element.base().hat_type, bitmap, name_and_size).unwrap();
+            assert_eq!(describe(element), describe(&*decoded), "{context}");
+        }
+    }
+}
+
+#[test]
+fn sample_hats_match_golden_files() {
+    let bless = std::env::var_os("HPP_BLESS").is_some();
+    let mut mismatched = vec![];
+    for hat_dir in sample_hat_dirs() {
+        let hat = Hat::load(&hat_dir).unwrap();
+        let mut actual = String::new();
+        for element in sorted_elements(&hat) {
+            let file_name = element.base().path.as_ref().and_then(|p| p.file_name());
+            let file_name = file_name.unwrap().to_string_lossy();
+            writeln!(actual, "# {file_name}").unwrap();
+            actual.push_str(&describe(element));
+        }
+
+        let golden_path = golden_path(&hat_dir);
+        if bless {
+            std::fs::create_dir_all(GOLDEN_DIR).unwrap();
+            std::fs::write(&golden_path, actual).unwrap();
+            continue;
+        }
+        let expected = std::fs::read_to_string(&golden_path).unwrap_or_default();
+        if expected != actual {
+            mismatched.push(golden_path.display().to_string());
+        }
+    }
+    assert!(
+        mismatched.is_empty(),
+        "decoded hats differ from the golden files, rerun with HPP_BLESS=1 if this is intended:\n{}",
+        mismatched.join("\n")
+    );
+}